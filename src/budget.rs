@@ -0,0 +1,215 @@
+/*!
+Module containing the budgets of a crawl.
+*/
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
+
+/// Limits of a crawl. No limits are set by default.
+#[derive(Clone, Debug, Default)]
+pub struct Budget {
+    pub max_pages: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub max_duration: Option<Duration>,
+    pub host_pages: Option<usize>,
+}
+
+/// Budget which stopped the crawl.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Limit {
+    #[serde(rename = "max_pages")]
+    Pages,
+    #[serde(rename = "max_bytes")]
+    Bytes,
+    #[serde(rename = "max_duration")]
+    Duration,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Pages => write!(f, "max pages"),
+            Limit::Bytes => write!(f, "max bytes"),
+            Limit::Duration => write!(f, "max duration"),
+        }
+    }
+}
+
+/// Decision on starting a page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Admission {
+    /// The page can be started.
+    Start,
+    /// The host of the page reached its quota.
+    SkipHost,
+    /// The crawl reached a budget, so no more pages are started.
+    Stop,
+}
+
+/// Use of the budget written at the end of the crawl.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BudgetReport {
+    pub pages: usize,
+    pub bytes: u64,
+    pub seconds: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped_by: Option<Limit>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub hosts_over_quota: BTreeSet<String>,
+}
+
+/// Pages started and budgets reached so far.
+#[derive(Debug, Default)]
+struct Usage {
+    pages: usize,
+    hosts: HashMap<String, usize>,
+    stopped_by: Option<Limit>,
+    hosts_over_quota: BTreeSet<String>,
+}
+
+/// Tracker of the use of the budget.
+/// The clock starts when the tracker is created.
+#[derive(Clone, Debug)]
+pub struct BudgetTracker {
+    budget: Budget,
+    start: Instant,
+    bytes: Arc<AtomicU64>,
+    usage: Arc<Mutex<Usage>>,
+}
+
+impl Default for BudgetTracker {
+    fn default() -> Self {
+        BudgetTracker::new(Budget::default())
+    }
+}
+
+impl BudgetTracker {
+    /// Creates a new BudgetTracker.
+    pub fn new(budget: Budget) -> Self {
+        BudgetTracker {
+            budget,
+            start: Instant::now(),
+            bytes: Arc::default(),
+            usage: Arc::default(),
+        }
+    }
+
+    /// Function to count the bytes downloaded.
+    pub fn add_bytes(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Function to decide if the page of a url can be started, counting it if it can.
+    /// Once a budget is reached no more pages are started.
+    pub fn admit(&self, url: &Url) -> Admission {
+        let mut usage = match self.usage.lock() {
+            Ok(x) => x,
+            Err(_) => return Admission::Stop,
+        };
+        if usage.stopped_by.is_some() {
+            return Admission::Stop;
+        }
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let limit = if self.budget.max_pages.is_some_and(|x| usage.pages >= x) {
+            Some(Limit::Pages)
+        } else if self.budget.max_bytes.is_some_and(|x| bytes >= x) {
+            Some(Limit::Bytes)
+        } else if self
+            .budget
+            .max_duration
+            .is_some_and(|x| self.start.elapsed() >= x)
+        {
+            Some(Limit::Duration)
+        } else {
+            None
+        };
+        if let Some(x) = limit {
+            usage.stopped_by = Some(x);
+            return Admission::Stop;
+        }
+
+        let host = url.host_str().unwrap_or_default().to_string();
+        let host_pages = usage.hosts.get(&host).copied().unwrap_or(0);
+        if self.budget.host_pages.is_some_and(|x| host_pages >= x) {
+            usage.hosts_over_quota.insert(host);
+            return Admission::SkipHost;
+        }
+        usage.hosts.insert(host, host_pages + 1);
+        usage.pages += 1;
+        Admission::Start
+    }
+
+    /// Function to get the time at which the maximum duration is reached, if one is set.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.budget.max_duration.map(|x| self.start + x)
+    }
+
+    /// Function to stop the crawl because of a budget, unless it was already stopped.
+    pub fn stop(&self, limit: Limit) {
+        if let Ok(mut x) = self.usage.lock() {
+            x.stopped_by.get_or_insert(limit);
+        }
+    }
+
+    /// Function to get the use of the budget so far.
+    pub fn report(&self) -> BudgetReport {
+        let usage = match self.usage.lock() {
+            Ok(x) => x,
+            Err(_) => return BudgetReport::default(),
+        };
+        BudgetReport {
+            pages: usage.pages,
+            bytes: self.bytes.load(Ordering::Relaxed),
+            seconds: self.start.elapsed().as_secs(),
+            stopped_by: usage.stopped_by,
+            hosts_over_quota: usage.hosts_over_quota.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_limits() {
+        let url = |x: &str| Url::parse(x).unwrap();
+        let tracker = BudgetTracker::new(Budget {
+            max_pages: Some(3),
+            host_pages: Some(1),
+            ..Budget::default()
+        });
+        assert_eq!(tracker.admit(&url("https://a.com/")), Admission::Start);
+        assert_eq!(tracker.admit(&url("https://a.com/x")), Admission::SkipHost);
+        assert_eq!(tracker.admit(&url("https://b.com/")), Admission::Start);
+        assert_eq!(tracker.admit(&url("https://c.com/")), Admission::Start);
+        assert_eq!(tracker.admit(&url("https://d.com/")), Admission::Stop);
+        assert_eq!(tracker.admit(&url("https://e.com/")), Admission::Stop);
+        let report = tracker.report();
+        assert_eq!(report.pages, 3);
+        assert_eq!(report.stopped_by, Some(Limit::Pages));
+        assert_eq!(report.hosts_over_quota.len(), 1);
+
+        let tracker = BudgetTracker::new(Budget {
+            max_bytes: Some(100),
+            ..Budget::default()
+        });
+        assert_eq!(tracker.admit(&url("https://a.com/")), Admission::Start);
+        tracker.add_bytes(100);
+        assert_eq!(tracker.admit(&url("https://a.com/x")), Admission::Stop);
+        assert_eq!(tracker.report().stopped_by, Some(Limit::Bytes));
+
+        let tracker = BudgetTracker::new(Budget {
+            max_duration: Some(Duration::from_secs(0)),
+            ..Budget::default()
+        });
+        assert_eq!(tracker.admit(&url("https://a.com/")), Admission::Stop);
+        assert_eq!(tracker.report().stopped_by, Some(Limit::Duration));
+    }
+}
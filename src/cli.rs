@@ -1,15 +1,33 @@
 /*!
 Module Containing the Command Line part of the Crawler.
 */
+use crate::budget::{Budget, BudgetTracker};
+use crate::crawler::RenderOptions;
+use crate::dedup::Duplicates;
 use crate::extractors::links::Link;
+use crate::extractors::rules::RuleSet;
+use crate::extractors::words::{SearchOptions, SearchTerms};
 use crate::file_handler;
+use crate::frontier::{score_fn, Order, Score, Strategy};
+use crate::http_cache::{CacheMode, HttpCache};
+use crate::mirror::Mirror;
+use crate::policy::NetworkPolicy;
+use crate::recrawl::{self, Recrawl};
+use crate::selenium::{handle_selenium, Browser, Renderer, SeleniumOptions, WindowSize};
+use crate::store::Store;
+use crate::traps::{TrapDetector, TrapLimits};
+use crate::triggers::{ContentHashes, Trigger, Triggers};
 use clap::Clap;
-use std::{collections::HashSet, path::PathBuf, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 use tokio::sync::mpsc;
 
 #[derive(Clap, Clone)]
 #[clap(version = "1.0", author = "Ayush Singh <ayushsingh1325@gmail.com>")]
-struct CLI {
+struct Cli {
     /// Seed url for crawler.
     url: String,
     /// Gives numeric depth for crawl.
@@ -22,8 +40,22 @@ struct CLI {
     #[clap(short, long)]
     blacklist: Option<PathBuf>,
     /// Path to file containing words to search for in the page.
+    /// Lines like /pattern/flags are regexes, with flags i for ignore case and w for whole word.
     #[clap(short, long)]
     search_words: Option<PathBuf>,
+    /// Ignore case when searching for words.
+    #[clap(long)]
+    ignore_case: bool,
+    /// Only match whole words when searching for words.
+    #[clap(long)]
+    whole_word: bool,
+    /// Search for words in the html markup instead of only the visible text.
+    #[clap(long)]
+    search_markup: bool,
+    /// Path to file containing named rules over the search words, like
+    /// leak: ("invoice" AND "password") NOT "example". Pages are flagged by the rules instead of any word.
+    #[clap(long)]
+    rules: Option<PathBuf>,
     /// Path to the output folder.
     #[clap(short, long)]
     output_folder: Option<PathBuf>,
@@ -34,19 +66,181 @@ struct CLI {
     #[clap(short, long, default_value = "10")]
     timeout: u64,
     /// Flag for taking screenshots using Selenium.
-    /// Takes screenshot if a word from wordlist is found in the page.
+    /// Takes screenshot if the page is flagged by the words or rules, or by the screenshot triggers.
     #[clap(long)]
     selenium: bool,
-    /// Limits the number of parallel tasks.
+    /// Url of the WebDriver server used for screenshots.
+    #[clap(long, default_value = "http://localhost:4444/wd/hub")]
+    webdriver_url: String,
+    /// Browser used for screenshots, chrome or firefox.
+    #[clap(long, default_value = "chrome")]
+    browser: Browser,
+    /// Run the browser without a window.
+    #[clap(long)]
+    headless: bool,
+    /// Size of the browser window like 1920x1080. The window is made fullscreen if not given.
+    #[clap(long)]
+    window_size: Option<WindowSize>,
+    /// Extra argument passed to the browser, like --browser-arg=--disable-gpu. Can be given multiple times.
+    #[clap(long)]
+    browser_arg: Vec<String>,
+    /// Timeout in seconds for loading a page in the browser.
+    #[clap(long, default_value = "30")]
+    page_load_timeout: u64,
+    /// Number of browser sessions used in parallel, for screenshots and for rendering each.
+    #[clap(long, default_value = "1")]
+    selenium_sessions: usize,
+    /// Take screenshots of the whole page instead of only the visible part.
+    #[clap(long)]
+    full_page: bool,
+    /// Save the rendered DOM of the page next to every screenshot.
+    #[clap(long)]
+    save_dom: bool,
+    /// Take screenshots of the pages for which a trigger fires, instead of the flagged pages.
+    /// Triggers are flagged, depth:N for pages at most N links from the seed url, url:REGEX,
+    /// status, status:404 or status:5xx for error status codes,
+    /// and changed for pages whose content changed since the last crawl into the output folder.
+    /// Can be given multiple times.
+    #[clap(long)]
+    screenshot_on: Vec<Trigger>,
+    /// Timeout in seconds for taking the screenshot of a page.
+    /// The session is replaced by a new one if it takes longer.
+    #[clap(long, default_value = "60")]
+    screenshot_timeout: u64,
+    /// Path to the screenshots folder of an earlier crawl to compare the new screenshots with.
+    /// Diff images of the pages which changed are written to screenshots/diff,
+    /// and the pages are reported in screenshots/visual_diff.json.
+    #[clap(long)]
+    compare_screenshots: Option<PathBuf>,
+    /// Share of the area of a screenshot which has to change for the page to be reported
+    /// by the visual diff, between 0 and 1.
+    #[clap(long, default_value = "0.01")]
+    diff_threshold: f64,
+    /// Render html pages in the browser and extract the links and words from the rendered DOM.
+    /// Cannot be used with --block-private or --block-range, which the browser does not follow.
+    #[clap(long)]
+    render: bool,
+    /// Only render pages matching the rule with this name. Can be given multiple times.
+    #[clap(long)]
+    render_rule: Vec<String>,
+    /// Only render pages with fewer links than this in their static html.
+    #[clap(long)]
+    render_min_links: Option<usize>,
+    /// Maximum time in seconds to wait for the network to become idle when rendering a page.
+    #[clap(long, default_value = "10")]
+    render_wait: u64,
+    /// Order of crawling the pages: bfs for breadth-first, dfs for depth-first,
+    /// or best for best-first by the scores of the urls.
+    #[clap(long, default_value = "bfs")]
+    order: Order,
+    /// Score of the urls for best-first order: words for the search words in the url,
+    /// sitemap for the priority in the sitemap, shallow for urls with fewer path segments,
+    /// and near for urls fewer links away from the seed url.
+    /// Scores are added up, and all of them are used if none are given. Can be given multiple times.
+    #[clap(long)]
+    score: Vec<Score>,
+    /// Let the hosts take turns when picking the next page to crawl.
+    #[clap(long)]
+    round_robin_hosts: bool,
+    /// Stop the crawl after this many pages.
+    #[clap(long)]
+    max_pages: Option<usize>,
+    /// Stop the crawl after downloading this many bytes of page bodies.
+    #[clap(long)]
+    max_bytes: Option<u64>,
+    /// Stop the crawl after this many seconds. The pages already started are finished.
+    #[clap(long)]
+    max_duration: Option<u64>,
+    /// Crawl at most this many pages of every host.
+    #[clap(long)]
+    max_pages_per_host: Option<usize>,
+    /// Cut urls longer than this many characters.
+    #[clap(long, default_value = "2048")]
+    max_url_length: usize,
+    /// Cut urls with more path segments than this.
+    #[clap(long, default_value = "20")]
+    max_path_depth: usize,
+    /// Cut urls with a path segment appearing more times than this.
+    #[clap(long, default_value = "3")]
+    max_segment_repeats: usize,
+    /// Cut urls of a path after this many distinct query strings of it.
+    #[clap(long, default_value = "100")]
+    max_query_variants: usize,
+    /// Stop expanding an url pattern after this many pages of it with the same content,
+    /// ignoring digits and whitespace.
+    #[clap(long, default_value = "10")]
+    max_similar_pages: usize,
+    /// Disable the detection of crawler traps.
+    #[clap(long)]
+    no_trap_detection: bool,
+    /// Re-crawl the pages of the last crawl into the output folder with conditional requests.
+    /// Pages not modified since then keep their links and word hits from the last crawl,
+    /// and pages are reported as new, changed, unchanged or gone.
+    /// The pages are only kept for the next crawl when this is given.
+    #[clap(long)]
+    recrawl: bool,
+    /// Minimum similarity of the text of two pages, from 0 to 1, to report them as near-duplicates.
+    #[clap(long, default_value = "0.9")]
+    near_duplicate_threshold: f64,
+    /// Path of an embedded database keeping the frontier and the seen urls on disk instead of
+    /// in memory, for crawls bigger than the memory. The database is cleared when the crawl starts.
+    #[clap(long)]
+    frontier_db: Option<PathBuf>,
+    /// Expected number of urls for a Bloom filter in front of the frontier database,
+    /// which answers most checks for new urls without reading the disk.
+    #[clap(long)]
+    bloom_size: Option<usize>,
+    /// Path of a folder keeping the http responses, which are answered from it while fresh.
+    #[clap(long)]
+    http_cache: Option<PathBuf>,
+    /// Seconds for which responses without Cache-Control or Expires headers are fresh.
+    #[clap(long, default_value = "3600")]
+    cache_ttl: u64,
+    /// Answer every request from the http cache, however old the response, without touching
+    /// the network. Urls not in the cache get a 504 response. Also disables the DNS lookups,
+    /// and cannot be used with --render or --selenium, which would load the pages from the network.
+    #[clap(long)]
+    offline: bool,
+    /// Save the body of every fetched page into a browsable mirror in the output folder,
+    /// with the links between saved pages rewritten to local paths and a manifest of the urls.
+    #[clap(long)]
+    archive: bool,
+    /// Only save bodies of this mime type in the mirror, like text/html, or image for every image type.
+    /// Can be given multiple times.
+    #[clap(long)]
+    archive_mime: Vec<String>,
+    /// Maximum number of pages crawled at the same time. Also the size of the channels between the tasks.
     #[clap(long, default_value = "1000")]
     task_limit: usize,
+    /// Maximum size of a page body in bytes. Bigger bodies are truncated.
+    #[clap(long, default_value = "10485760")]
+    max_body_size: usize,
+    /// Also perform reverse DNS (PTR) lookups for the addresses of every host.
+    #[clap(long)]
+    reverse_dns: bool,
+    /// Nameserver to use instead of the system configuration.
+    /// Format is [udp|tcp|tls|https://]ip[:port][#tls-name]. Can be given multiple times.
+    #[clap(long)]
+    nameserver: Vec<crate::dns::NameServer>,
+    /// Path of file containing DNS overrides in hosts file format.
+    #[clap(long)]
+    dns_hosts: Option<PathBuf>,
+    /// Disable the DNS lookups for hosts and mail domains.
+    #[clap(long)]
+    no_dns: bool,
+    /// Refuse to connect to private, loopback and link-local addresses.
+    #[clap(long)]
+    block_private: bool,
+    /// Refuse to connect to addresses in a CIDR range. Can be given multiple times.
+    #[clap(long)]
+    block_range: Vec<ipnet::IpNet>,
 }
 
 /// Funtion that servers as the entry point to the Command Line Tool.
 /// It parses the arguments and fires off the methods.
 pub async fn entry() {
     let start_time = Instant::now();
-    let opts = CLI::parse();
+    let opts = Cli::parse();
     println!("Started");
     let task_limit = opts.task_limit;
     let (tx_output, rx_output) = mpsc::channel(task_limit);
@@ -54,13 +248,14 @@ pub async fn entry() {
 
     let output_folder = opts.output_folder.clone();
     let verbose = opts.verbose;
-    let url = opts.url.clone();
-    let depth = opts.depth;
-    let whitelist = opts.whitelist;
-    let blacklist = opts.blacklist;
-    let search_words = opts.search_words;
-    let timeout = opts.timeout;
-    let selenium = opts.selenium;
+    let selenium = if opts.selenium {
+        Some(selenium_options(&opts))
+    } else {
+        None
+    };
+
+    let compare_screenshots = opts.compare_screenshots.clone();
+    let diff_threshold = opts.diff_threshold;
 
     let output_folder_clone = output_folder.clone();
 
@@ -68,20 +263,8 @@ pub async fn entry() {
         handle_output(output_folder_clone, verbose, rx_output, task_limit).await
     });
 
-    let crawler_handler = tokio::spawn(async move {
-        launch_crawler(
-            url,
-            depth,
-            task_limit,
-            tx_output,
-            tx_selenium,
-            whitelist,
-            blacklist,
-            search_words,
-            timeout,
-        )
-        .await
-    });
+    let crawler_handler =
+        tokio::spawn(async move { launch_crawler(opts, tx_output, tx_selenium).await });
 
     let output_folder_clone = output_folder.clone();
     let selenium_handler =
@@ -96,27 +279,47 @@ pub async fn entry() {
         println!("Error : {}", x);
     }
 
+    if let (Some(previous), Some(folder)) = (compare_screenshots, output_folder) {
+        let current = folder.join("screenshots");
+        match crate::visual_diff::compare_screenshots(previous, current, diff_threshold).await {
+            Ok(x) => println!("Visually Changed Pages: {}", x.changed.len()),
+            Err(x) => println!("Error : {}", x),
+        }
+    }
+
     println!("Time Taken: {} seconds", start_time.elapsed().as_secs());
 }
 
+/// Function to get the options of the browser sessions.
+fn selenium_options(opts: &Cli) -> SeleniumOptions {
+    SeleniumOptions {
+        webdriver_url: opts.webdriver_url.clone(),
+        browser: opts.browser,
+        headless: opts.headless,
+        window_size: opts.window_size,
+        browser_args: opts.browser_arg.clone(),
+        page_load_timeout: opts.page_load_timeout,
+        sessions: opts.selenium_sessions,
+        screenshot_timeout: opts.screenshot_timeout,
+        full_page: opts.full_page,
+        save_dom: opts.save_dom,
+        policy: NetworkPolicy::new(opts.block_private, &opts.block_range),
+    }
+}
+
 /// Funtion to launch the crawler. Fires off the correct crawler method depending on the arguments.
 async fn launch_crawler(
-    origin_url: String,
-    depth: Option<usize>,
-    task_limit: usize,
+    opts: Cli,
     tx_output: mpsc::Sender<Link>,
-    tx_selenium: mpsc::Sender<String>,
-    whitelist: Option<PathBuf>,
-    blacklist: Option<PathBuf>,
-    search_words: Option<PathBuf>,
-    timeout: u64,
+    tx_selenium: mpsc::Sender<Link>,
 ) -> Result<(), String> {
-    let origin_url = match Link::new_from_str(origin_url.as_str()) {
+    let origin_url = match Link::new_from_str(opts.url.as_str()) {
         Some(x) => x,
         None => return Err("Invalid Url".to_string()),
     };
+    let webdriver = selenium_options(&opts);
 
-    let whitelist = match whitelist {
+    let whitelist = match opts.whitelist {
         Some(x) => match file_handler::read_hosts(x).await {
             Ok(y) => Some(y),
             Err(_) => return Err("Error in reading Whitelist".to_string()),
@@ -124,7 +327,7 @@ async fn launch_crawler(
         None => None,
     };
 
-    let blacklist = match blacklist {
+    let blacklist = match opts.blacklist {
         Some(x) => match file_handler::read_hosts(x).await {
             Ok(y) => Some(y),
             Err(_) => return Err("Error in reading Blacklist".to_string()),
@@ -132,85 +335,239 @@ async fn launch_crawler(
         None => None,
     };
 
-    let word_list = match search_words {
+    let rule_lines = match opts.rules {
+        Some(x) => match file_handler::read_lines(x).await {
+            Ok(x) => x,
+            Err(_) => return Err("Error in reading Rules".to_string()),
+        },
+        None => Vec::new(),
+    };
+    let rules = RuleSet::new(rule_lines.clone())?;
+
+    let mut word_list = match opts.search_words {
         Some(x) => match file_handler::read_words(x).await {
             Ok(x) => x,
             Err(_) => return Err("Error in reading Word List".to_string()),
         },
         None => HashSet::new(),
     };
+    word_list.extend(rules.terms());
+    let search_options = SearchOptions {
+        ignore_case: opts.ignore_case,
+        whole_word: opts.whole_word,
+        search_markup: opts.search_markup,
+    };
+    let settings = recrawl::settings_hash(&word_list, &rule_lines, &search_options);
+    let word_list = SearchTerms::new(word_list, &search_options)?;
+    if !opts.score.is_empty() && opts.order != Order::Best {
+        return Err("Scores need the best order".to_string());
+    }
+    let strategy = Strategy {
+        order: opts.order,
+        round_robin: opts.round_robin_hosts,
+        score: score_fn(&opts.score, word_list.clone()),
+    };
 
-    let handler = match depth {
-        None => {
-            crate::crawler::crawl_no_depth(
-                origin_url,
-                whitelist,
-                blacklist,
-                word_list,
-                tx_output,
-                tx_selenium,
-                task_limit,
-                timeout,
-            )
-            .await
+    let dns_overrides = match opts.dns_hosts {
+        Some(x) => match file_handler::read_dns_overrides(x).await {
+            Ok(y) => y,
+            Err(_) => return Err("Error in reading DNS Hosts".to_string()),
+        },
+        None => HashMap::new(),
+    };
+
+    let host_cache = crate::dns::HostCache::new(opts.reverse_dns);
+
+    if let Some(x) = opts.render_rule.iter().find(|x| !rules.has_rule(x)) {
+        return Err(format!("Unknown rule {}", x));
+    }
+    if opts.render && (opts.block_private || !opts.block_range.is_empty()) {
+        return Err("Rendering cannot be used with a network policy".to_string());
+    }
+    let render = if opts.render {
+        Some(RenderOptions {
+            renderer: Renderer::new(webdriver, opts.render_wait),
+            rules: opts.render_rule.clone(),
+            min_links: opts.render_min_links,
+        })
+    } else {
+        None
+    };
+    let renderer = render.as_ref().map(|x| x.renderer.clone());
+
+    let previous_hashes = match &opts.output_folder {
+        Some(x) => match file_handler::read_content_hashes(x.clone()).await {
+            Ok(y) => y,
+            Err(_) => return Err("Error in reading Content Hashes".to_string()),
+        },
+        None => HashMap::new(),
+    };
+    let content_hashes = ContentHashes::new(previous_hashes);
+    let budget = BudgetTracker::new(Budget {
+        max_pages: opts.max_pages,
+        max_bytes: opts.max_bytes,
+        max_duration: opts.max_duration.map(Duration::from_secs),
+        host_pages: opts.max_pages_per_host,
+    });
+
+    let traps = if opts.no_trap_detection {
+        TrapDetector::default()
+    } else {
+        TrapDetector::new(TrapLimits {
+            max_url_length: Some(opts.max_url_length),
+            max_path_depth: Some(opts.max_path_depth),
+            max_segment_repeats: Some(opts.max_segment_repeats),
+            max_query_variants: Some(opts.max_query_variants),
+            max_similar_pages: Some(opts.max_similar_pages),
+        })
+    };
+
+    let duplicates = Duplicates::new(opts.near_duplicate_threshold);
+    let recrawl = match (&opts.output_folder, opts.recrawl) {
+        (Some(x), true) => match file_handler::read_pages(x.clone()).await {
+            Ok(y) => Recrawl::new(y, settings),
+            Err(_) => return Err("Error in reading Pages".to_string()),
+        },
+        (Some(_), false) | (None, false) => Recrawl::default(),
+        (None, true) => return Err("Re-crawling needs an output folder".to_string()),
+    };
+
+    let http_cache = match (&opts.http_cache, opts.offline) {
+        (Some(_), true) if opts.render || opts.selenium => {
+            return Err("Offline replay cannot render pages or take screenshots".to_string())
         }
+        (Some(x), offline) => Some(HttpCache::new(
+            x.clone(),
+            if offline {
+                CacheMode::Offline
+            } else {
+                CacheMode::Normal
+            },
+            Duration::from_secs(opts.cache_ttl),
+            opts.max_body_size,
+        )),
+        (None, true) => return Err("Offline replay needs an http cache".to_string()),
+        (None, false) => None,
+    };
+
+    let mirror = match (&opts.output_folder, opts.archive) {
+        (Some(x), true) => Some(Mirror::new(x.join("mirror"), opts.archive_mime.clone())),
+        (None, true) => return Err("Archiving needs an output folder".to_string()),
+        (_, false) => None,
+    };
+
+    let store = match (&opts.frontier_db, opts.bloom_size) {
+        (Some(x), bloom_size) => Store::open(x, bloom_size)?,
+        (None, Some(_)) => return Err("Bloom filters need a frontier database".to_string()),
+        (None, None) => Store::memory(),
+    };
+
+    let options = crate::crawler::CrawlOptions {
+        whitelist,
+        blacklist,
+        word_list,
+        rules,
+        task_limit: opts.task_limit,
+        timeout: opts.timeout,
+        max_body_size: opts.max_body_size,
+        host_cache: host_cache.clone(),
+        resolver: crate::dns::ResolverOptions {
+            nameservers: opts.nameserver,
+            overrides: dns_overrides,
+            policy: NetworkPolicy::new(opts.block_private, &opts.block_range),
+        },
+        dns_lookups: !opts.no_dns && !opts.offline,
+        render,
+        triggers: Triggers::new(opts.screenshot_on),
+        content_hashes: content_hashes.clone(),
+        strategy,
+        budget: budget.clone(),
+        store,
+        traps: traps.clone(),
+        duplicates: duplicates.clone(),
+        recrawl: recrawl.clone(),
+        http_cache,
+        mirror: mirror.clone(),
+    };
+
+    let handler = match opts.depth {
+        None => crate::crawler::crawl_no_depth(origin_url, options, tx_output, tx_selenium).await,
 
         Some(x) => {
-            crate::crawler::crawl_with_depth(
-                origin_url,
-                x,
-                whitelist,
-                blacklist,
-                word_list,
-                tx_output,
-                tx_selenium,
-                task_limit,
-                timeout,
-            )
-            .await
+            crate::crawler::crawl_with_depth(origin_url, x, options, tx_output, tx_selenium).await
         }
     };
-    match handler {
-        Ok(_) => Ok(()),
-        Err(_) => Err("Something went wrong in the Crawler".to_string()),
+    if let Some(x) = renderer {
+        x.close().await;
+    }
+    if handler.is_err() {
+        return Err("Something went wrong in the Crawler".to_string());
+    }
+    let budget = budget.report();
+    if let Some(x) = budget.stopped_by {
+        println!("Stopped by the budget: {}", x);
+    }
+    let duplicates = duplicates.report();
+    println!(
+        "Duplicate Pages: {}, Near-Duplicate Clusters: {}",
+        duplicates.exact.values().map(|x| x.len()).sum::<usize>(),
+        duplicates.near.len()
+    );
+    if let Some(x) = mirror {
+        match x.finish().await {
+            Ok(y) => println!("Archived Pages: {}", y),
+            Err(_) => return Err("Error in writing Archive".to_string()),
+        }
+    }
+    let recrawl_report = recrawl.report();
+    if recrawl.is_enabled() {
+        println!(
+            "New Pages: {}, Changed: {}, Unchanged: {}, Gone: {}",
+            recrawl_report.new.len(),
+            recrawl_report.changed.len(),
+            recrawl_report.unchanged.len(),
+            recrawl_report.gone.len()
+        );
+    }
+    let cuts = traps.cuts();
+    if !cuts.is_empty() {
+        println!(
+            "Cut by the trap detection: {} URLs",
+            cuts.iter().map(|x| x.urls).sum::<usize>()
+        );
     }
-}
-
-/// Function to handle selenium.
-/// Uses the chrome/chromium browser for now.
-async fn handle_selenium(
-    file_path: Option<PathBuf>,
-    flag: bool,
-    mut rx: mpsc::Receiver<String>,
-) -> Result<(), thirtyfour::error::WebDriverError> {
-    use thirtyfour::prelude::*;
-    use tokio::fs;
-
-    if flag {
-        if let Some(file_path) = file_path {
-            let mut caps = DesiredCapabilities::chrome();
-            caps.add_chrome_arg("--enable-automation")?;
-            let driver = WebDriver::new("http://localhost:4444/wd/hub", &caps).await?;
-            // driver.fullscreen_window().await?;
-            let mut file_name = 1;
-            let mut file_path = file_path.clone();
-            file_path.push("screenshots");
-            if fs::create_dir_all(&file_path).await.is_err() {
-                return Err(thirtyfour::error::WebDriverError::FatalError(
-                    "".to_string(),
-                ));
-            }
 
-            while let Some(link) = rx.recv().await {
-                if driver.get(link.as_str()).await.is_ok() {
-                    let mut img_path = file_path.clone();
-                    img_path.push(file_name.to_string());
-                    let _ = driver.fullscreen_window().await;
-                    let _ = driver.screenshot(&img_path).await;
-                    file_name += 1;
-                }
-            }
-            let _ = driver.quit().await;
+    if let Some(x) = opts.output_folder {
+        file_handler::write_json_file(x.clone(), "hosts.json", &host_cache.hosts())
+            .await
+            .map_err(|_| "Error in writing Hosts".to_string())?;
+        file_handler::write_json_file(
+            x.clone(),
+            file_handler::CONTENT_HASHES_FILE_NAME,
+            &content_hashes.hashes(),
+        )
+        .await
+        .map_err(|_| "Error in writing Content Hashes".to_string())?;
+        file_handler::write_json_file(x.clone(), "budget.json", &budget)
+            .await
+            .map_err(|_| "Error in writing Budget".to_string())?;
+        file_handler::write_json_file(x.clone(), "traps.json", &cuts)
+            .await
+            .map_err(|_| "Error in writing Traps".to_string())?;
+        file_handler::write_json_file(x.clone(), "duplicates.json", &duplicates)
+            .await
+            .map_err(|_| "Error in writing Duplicates".to_string())?;
+        if recrawl.is_enabled() {
+            file_handler::write_json_file(
+                x.clone(),
+                file_handler::PAGES_FILE_NAME,
+                &recrawl.pages(),
+            )
+            .await
+            .map_err(|_| "Error in writing Pages".to_string())?;
+            file_handler::write_json_file(x, "recrawl.json", &recrawl_report)
+                .await
+                .map_err(|_| "Error in writing Recrawl".to_string())?;
         }
     }
     Ok(())
@@ -240,7 +597,7 @@ async fn handle_output(
     }
     while let Some(link) = rx.recv().await {
         for i in &senders {
-            if let Err(_) = i.send(link.clone()).await {
+            if i.send(link.clone()).await.is_err() {
                 let _ = futures::future::try_join_all(handlers).await;
                 return Err("Something Wrong with IO".to_string());
             }
@@ -261,6 +618,6 @@ async fn write_standard_output(mut rx: mpsc::Receiver<Link>) -> Result<(), std::
     while let Some(link) = rx.recv().await {
         println!("{},", link);
     }
-    println!("");
+    println!();
     Ok(())
 }
@@ -1,18 +1,157 @@
 /*!
 Module Containing the Crawler functions.
 */
-use crate::extractors::links;
+use crate::budget::{self, Admission};
+use crate::dedup;
+use crate::dns;
+use crate::extractors::{content, links, rules, words};
+use crate::frontier::{Frontier, Strategy};
+use crate::http_cache::HttpCache;
+use crate::mirror::{Mirror, MirrorEntry};
+use crate::policy;
+use crate::recrawl;
+use crate::selenium;
+use crate::store::{Store, UrlSet};
+use crate::traps;
+use crate::triggers;
 use futures::{stream, StreamExt};
 use links::Link;
 use reqwest::Url;
 use std::time::Duration;
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
 use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+/// Maximum number of sitemaps crawled, counting the ones listed in sitemap indexes.
+const MAX_SITEMAPS: usize = 100;
+
+/// Options for the crawler.
+pub struct CrawlOptions {
+    pub whitelist: Option<HashSet<url::Host>>,
+    pub blacklist: Option<HashSet<url::Host>>,
+    pub word_list: words::SearchTerms,
+    pub rules: rules::RuleSet,
+    pub task_limit: usize,
+    pub timeout: u64,
+    pub max_body_size: usize,
+    pub host_cache: dns::HostCache,
+    pub resolver: dns::ResolverOptions,
+    pub dns_lookups: bool,
+    pub render: Option<RenderOptions>,
+    pub triggers: triggers::Triggers,
+    pub content_hashes: triggers::ContentHashes,
+    pub strategy: Strategy,
+    pub budget: budget::BudgetTracker,
+    pub store: Store,
+    pub traps: traps::TrapDetector,
+    pub duplicates: dedup::Duplicates,
+    pub recrawl: recrawl::Recrawl,
+    pub http_cache: Option<HttpCache>,
+    pub mirror: Option<Mirror>,
+}
+
+/// Options for rendering html pages in the browser before extracting from them.
+/// Every html page is rendered if no rules and no minimum number of links are given.
+/// Otherwise pages are rendered if they match one of the rules,
+/// or if they have fewer links than the minimum.
+#[derive(Clone)]
+pub struct RenderOptions {
+    pub renderer: selenium::Renderer,
+    pub rules: Vec<String>,
+    pub min_links: Option<usize>,
+}
+
+impl RenderOptions {
+    /// Function to check if a page should be rendered, going by its static html.
+    fn should_render(
+        &self,
+        html: &str,
+        url: &str,
+        word_list: &words::SearchTerms,
+        rules: &rules::RuleSet,
+    ) -> bool {
+        if self.rules.is_empty() && self.min_links.is_none() {
+            return true;
+        }
+        let few_links = self
+            .min_links
+            .is_some_and(|x| links::get_links_from_html(html, url).len() < x);
+        few_links
+            || (!self.rules.is_empty()
+                && rules
+                    .evaluate(&word_list.search_html(html))
+                    .iter()
+                    .any(|x| self.rules.contains(x)))
+    }
+}
+
+/// State shared between the tasks crawling single pages.
+#[derive(Clone)]
+struct CrawlContext {
+    client: reqwest::Client,
+    resolver: dns::Resolver,
+    dns_lookups: bool,
+    word_list: Arc<words::SearchTerms>,
+    rules: Arc<rules::RuleSet>,
+    mail_cache: dns::MailDomainCache,
+    host_cache: dns::HostCache,
+    tx_selenium: mpsc::Sender<Link>,
+    task_limit: usize,
+    max_body_size: usize,
+    render: Option<Arc<RenderOptions>>,
+    triggers: Arc<triggers::Triggers>,
+    content_hashes: triggers::ContentHashes,
+    budget: budget::BudgetTracker,
+    traps: traps::TrapDetector,
+    duplicates: dedup::Duplicates,
+    recrawl: recrawl::Recrawl,
+    http_cache: Option<HttpCache>,
+    mirror: Option<Mirror>,
+}
+
+impl CrawlContext {
+    /// Creates a new CrawlContext from the crawler options.
+    fn new(options: &CrawlOptions, tx_selenium: mpsc::Sender<Link>) -> Result<Self, String> {
+        let resolver = init_dns_resolver(&options.resolver)?;
+        Ok(CrawlContext {
+            client: init_reqwest_client(options.timeout, &resolver)?,
+            resolver,
+            dns_lookups: options.dns_lookups,
+            word_list: Arc::new(options.word_list.clone()),
+            rules: Arc::new(options.rules.clone()),
+            mail_cache: dns::MailDomainCache::default(),
+            host_cache: options.host_cache.clone(),
+            tx_selenium,
+            task_limit: options.task_limit,
+            max_body_size: options.max_body_size,
+            render: options.render.clone().map(Arc::new),
+            triggers: Arc::new(options.triggers.clone()),
+            content_hashes: options.content_hashes.clone(),
+            budget: options.budget.clone(),
+            traps: options.traps.clone(),
+            duplicates: options.duplicates.clone(),
+            recrawl: options.recrawl.clone(),
+            http_cache: options.http_cache.clone(),
+            mirror: options.mirror.clone(),
+        })
+    }
+}
 
 /// Function to initialize Reqwest Client.
-/// Also specifies the timeout.
-fn init_reqwest_client(timeout: u64) -> Result<reqwest::Client, String> {
-    let client_builder = reqwest::ClientBuilder::new().timeout(Duration::new(timeout, 0));
+/// Also specifies the timeout, the DNS resolver and the network policy for redirects.
+/// System proxies are not used with a network policy, as the resolver would never see the hosts.
+fn init_reqwest_client(timeout: u64, resolver: &dns::Resolver) -> Result<reqwest::Client, String> {
+    let mut client_builder = reqwest::ClientBuilder::new()
+        .timeout(Duration::new(timeout, 0))
+        .dns_resolver(Arc::new(resolver.clone()));
+    if !resolver.policy().is_empty() {
+        client_builder = client_builder
+            .redirect(resolver.policy().redirect_policy())
+            .no_proxy();
+    }
     match client_builder.build() {
         Ok(x) => Ok(x),
         Err(_) => Err("Could not build http client".to_string()),
@@ -20,241 +159,454 @@ fn init_reqwest_client(timeout: u64) -> Result<reqwest::Client, String> {
 }
 
 /// Function to initialize DNS resolver.
-fn init_dns_resolver() -> Result<trust_dns_resolver::TokioAsyncResolver, String> {
-    match trust_dns_resolver::TokioAsyncResolver::tokio_from_system_conf() {
-        Ok(x) => Ok(x),
-        Err(_) => Err("Could not build dns resolver".to_string()),
-    }
+fn init_dns_resolver(options: &dns::ResolverOptions) -> Result<dns::Resolver, String> {
+    dns::Resolver::new(options)
 }
 
 /// Funtion to start crawling when depth is specified.
+/// Pages are crawled up to the depth, and the links found at the depth are output as not crawled.
 /// Does not use Sitemaps.
 pub async fn crawl_with_depth(
     origin_url: Link,
     crawl_depth: usize,
-    whitelist: Option<HashSet<url::Host>>,
-    blacklist: Option<HashSet<url::Host>>,
-    word_list: HashSet<String>,
+    options: CrawlOptions,
     tx_output: mpsc::Sender<Link>,
-    tx_selenium: mpsc::Sender<String>,
-    task_limit: usize,
-    timeout: u64,
+    tx_selenium: mpsc::Sender<Link>,
 ) -> Result<(), String> {
-    let mut to_crawl: HashSet<Url> = HashSet::new();
-    let mut crawled: HashSet<Url> = HashSet::new();
-    let mut dont_crawl: HashSet<Url> = HashSet::new();
-    let word_list = Arc::new(word_list);
-
-    let client = init_reqwest_client(timeout)?;
-    let resolver = init_dns_resolver()?;
-
-    to_crawl.insert(origin_url.url);
-
-    for _ in 0..crawl_depth {
-        println!("Crawling {} URls", to_crawl.len());
-
-        let (tx_cralwer, mut rx_crawler) = mpsc::channel::<Link>(task_limit);
-
-        to_crawl.iter().cloned().for_each(|x| {
-            let tx_clone = tx_cralwer.clone();
-            let tx_selenium_clone = tx_selenium.clone();
-            let client_clone = client.clone();
-            let resolver_clone = resolver.clone();
-            let word_list_clone = word_list.clone();
-            tokio::spawn(async move {
-                crawl_page(
-                    x,
-                    client_clone,
-                    tx_clone,
-                    tx_selenium_clone,
-                    task_limit,
-                    resolver_clone,
-                    word_list_clone,
-                )
-                .await
-            });
-        });
-
-        to_crawl.clear();
-
-        drop(tx_cralwer);
-
-        while let Some(link) = rx_crawler.recv().await {
-            if link.crawled {
-                crawled.insert(link.url.clone());
-                if let Err(_) = tx_output.send(link).await {
-                    return Err("Output Connection Closed".to_string());
-                }
-            } else {
-                let should_crawl = link.should_crawl(&whitelist, &blacklist);
-                if should_crawl && !crawled.contains(&link.url) {
-                    to_crawl.insert(link.url);
-                } else if !should_crawl && !dont_crawl.contains(&link.url) {
-                    dont_crawl.insert(link.url.clone());
-                    if let Err(_) = tx_output.send(link).await {
-                        return Err("Output Connection Closed".to_string());
-                    }
-                }
-            }
-        }
-    }
-
-    stream::iter(to_crawl)
-        .map(|x| links::Link::new_from_url(&x))
-        .for_each_concurrent(task_limit, |x| async {
-            let _ = tx_output.send(x).await;
-        })
-        .await;
-    Ok(())
+    crawl(
+        origin_url,
+        Some(crawl_depth),
+        false,
+        options,
+        tx_output,
+        tx_selenium,
+    )
+    .await
 }
 
 /// Function to crawl when depth is not specified.
 /// Also makes use of Sitemaps.
 pub async fn crawl_no_depth(
     origin_url: Link,
-    whitelist: Option<HashSet<url::Host>>,
-    blacklist: Option<HashSet<url::Host>>,
-    word_list: HashSet<String>,
+    options: CrawlOptions,
     tx_output: mpsc::Sender<Link>,
-    tx_selenium: mpsc::Sender<String>,
-    task_limit: usize,
-    timeout: u64,
+    tx_selenium: mpsc::Sender<Link>,
 ) -> Result<(), String> {
-    let mut to_crawl: HashSet<Url> = HashSet::new();
-    let mut crawled: HashSet<Url> = HashSet::new();
-    let mut dont_crawl: HashSet<Url> = HashSet::new();
-    let word_list = Arc::new(word_list);
-
-    let client = init_reqwest_client(timeout)?;
-    let resolver = init_dns_resolver()?;
-
-    to_crawl.insert(origin_url.url.clone());
+    crawl(origin_url, None, true, options, tx_output, tx_selenium).await
+}
 
-    let mut first_crawl = true;
+/// Function to crawl from the seed url until the frontier is empty or a budget is reached.
+/// At most task_limit pages are crawled at a time, and a new page is started as soon as
+/// one finishes, so the crawl is not held up by the slowest page of a depth.
+/// Pages keep their place until their links are received, so full channels slow down the crawl.
+async fn crawl(
+    origin_url: Link,
+    max_depth: Option<usize>,
+    sitemaps: bool,
+    options: CrawlOptions,
+    tx_output: mpsc::Sender<Link>,
+    tx_selenium: mpsc::Sender<Link>,
+) -> Result<(), String> {
+    let task_limit = options.task_limit.max(1);
+    let context = CrawlContext::new(&options, tx_selenium)?;
+    let mut state = CrawlState {
+        frontier: Frontier::new(options.strategy.clone(), &options.store)?,
+        dont_crawl: options.store.url_set("dont_crawl")?,
+        too_deep: options.store.url_set("too_deep")?,
+        skipped: options.store.url_set("skipped")?,
+        trapped: options.store.url_set("trapped")?,
+        max_depth,
+    };
+    let (tx_crawler, mut rx_crawler) = mpsc::channel::<Link>(task_limit);
+    let mut workers = JoinSet::new();
 
-    while !to_crawl.is_empty() {
-        println!("Crawling {} URls", to_crawl.len());
+    if max_depth == Some(0) {
+        state.too_deep.insert(origin_url.url.clone())?;
+    } else {
+        state.frontier.push(origin_url.url.clone(), 0, None)?;
+    }
+    // The sitemaps are fetched next to the workers, so they do not take a page's slot.
+    let mut sitemap_task = if sitemaps {
+        let client = context.client.clone();
+        let cache = context.http_cache.clone();
+        let tx = tx_crawler.clone();
+        Some(tokio::spawn(crawl_sitemaps(
+            origin_url.url,
+            tx,
+            task_limit,
+            client,
+            cache,
+        )))
+    } else {
+        None
+    };
 
-        let (tx_cralwer, mut rx_crawler) = mpsc::channel::<Link>(task_limit);
+    let deadline = options
+        .budget
+        .deadline()
+        .map(tokio::time::Instant::from_std);
+    let mut crawling_depth = None;
+    let mut stopped = false;
+    loop {
+        while !stopped && workers.len() < task_limit {
+            let (url, depth) = match state.frontier.pop()? {
+                Some(x) => x,
+                None => break,
+            };
+            match options.budget.admit(&url) {
+                Admission::Start => {}
+                Admission::SkipHost => {
+                    state.skipped.insert(url)?;
+                    continue;
+                }
+                Admission::Stop => {
+                    state.skipped.insert(url)?;
+                    stopped = true;
+                    if let Some(x) = &sitemap_task {
+                        x.abort();
+                    }
+                    break;
+                }
+            }
+            if !matches!(crawling_depth, Some(x) if depth <= x) {
+                println!(
+                    "Crawling depth {}, {} URls waiting",
+                    depth,
+                    state.frontier.len()
+                );
+                crawling_depth = Some(depth);
+            }
+            workers.spawn(crawl_page(url, depth, context.clone(), tx_crawler.clone()));
+        }
 
-        if first_crawl {
-            let tx_clone = tx_cralwer.clone();
-            let client_clone = client.clone();
-            let url = origin_url.url.clone();
-            tokio::spawn(async move {
-                crawl_sitemaps(url, tx_clone, task_limit, client_clone).await;
-            });
-            first_crawl = false;
+        if workers.is_empty() && sitemap_task.is_none() {
+            // Every task has finished, so all the links they found are in the channel.
+            while let Ok(link) = rx_crawler.try_recv() {
+                state.receive(link, &options, &tx_output).await?;
+            }
+            if stopped || state.frontier.is_empty() {
+                break;
+            }
+            continue;
         }
 
-        to_crawl.iter().cloned().for_each(|x| {
-            let tx_clone = tx_cralwer.clone();
-            let tx_selenium_clone = tx_selenium.clone();
-            let client_clone = client.clone();
-            let resolver_clone = resolver.clone();
-            let word_list_clone = word_list.clone();
-            tokio::spawn(async move {
-                crawl_page(
-                    x,
-                    client_clone,
-                    tx_clone,
-                    tx_selenium_clone,
-                    task_limit,
-                    resolver_clone,
-                    word_list_clone,
-                )
-                .await
-            });
-        });
+        tokio::select! {
+            Some(link) = rx_crawler.recv() => state.receive(link, &options, &tx_output).await?,
+            _ = workers.join_next(), if !workers.is_empty() => {}
+            _ = async {
+                if let Some(x) = sitemap_task.as_mut() {
+                    let _ = x.await;
+                }
+            }, if sitemap_task.is_some() => sitemap_task = None,
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)),
+                if !stopped && deadline.is_some() => {
+                options.budget.stop(budget::Limit::Duration);
+                stopped = true;
+                if let Some(x) = &sitemap_task {
+                    x.abort();
+                }
+            }
+        }
+    }
 
-        to_crawl.clear();
+    let mut not_crawled = Vec::new();
+    for x in state.too_deep.urls()? {
+        if !state.frontier.is_visited(&x)? {
+            not_crawled.push(x);
+        }
+    }
+    not_crawled.extend(state.skipped.urls()?);
+    for x in not_crawled {
+        let _ = tx_output.send(links::Link::new_from_url(&x)).await;
+    }
+    while let Some((x, _)) = state.frontier.pop()? {
+        let _ = tx_output.send(links::Link::new_from_url(&x)).await;
+    }
+    Ok(())
+}
 
-        drop(tx_cralwer);
+/// State of the crawl kept by the loop receiving the links from the tasks.
+/// Urls skipped by the budget are kept to be output as not crawled.
+struct CrawlState {
+    frontier: Frontier,
+    dont_crawl: UrlSet,
+    too_deep: UrlSet,
+    skipped: UrlSet,
+    trapped: UrlSet,
+    max_depth: Option<usize>,
+}
 
-        while let Some(link) = rx_crawler.recv().await {
-            if link.crawled {
-                crawled.insert(link.url.clone());
-                if let Err(_) = tx_output.send(link).await {
-                    return Err("Output Connection Closed".to_string());
-                }
+impl CrawlState {
+    /// Function to handle a link from the tasks.
+    /// Crawled pages and links which should not be crawled are output,
+    /// and the other links are added to the frontier, if they are not too deep.
+    /// Links cut by the trap detection are output as not crawled.
+    async fn receive(
+        &mut self,
+        link: Link,
+        options: &CrawlOptions,
+        tx_output: &mpsc::Sender<Link>,
+    ) -> Result<(), String> {
+        if link.crawled {
+            if tx_output.send(link).await.is_err() {
+                return Err("Output Connection Closed".to_string());
+            }
+            return Ok(());
+        }
+        let should_crawl = link.should_crawl(&options.whitelist, &options.blacklist);
+        if should_crawl {
+            let trap = if self.frontier.is_visited(&link.url)? {
+                None
             } else {
-                let should_crawl = link.should_crawl(&whitelist, &blacklist);
-                if should_crawl && !crawled.contains(&link.url) {
-                    to_crawl.insert(link.url);
-                } else if !should_crawl && !dont_crawl.contains(&link.url) {
-                    dont_crawl.insert(link.url.clone());
-                    if let Err(_) = tx_output.send(link).await {
+                options.traps.check_url(&link.url)
+            };
+            if let Some(x) = trap {
+                if self.trapped.insert(link.url.clone())? {
+                    options.traps.record(&link.url, x);
+                    if tx_output.send(link).await.is_err() {
                         return Err("Output Connection Closed".to_string());
                     }
                 }
+            } else if self.max_depth.is_some_and(|x| link.depth >= x) {
+                self.too_deep.insert(link.url)?;
+            } else {
+                self.frontier
+                    .push(link.url, link.depth, link.sitemap_priority)?;
             }
+        } else if self.dont_crawl.insert(link.url.clone())? && tx_output.send(link).await.is_err() {
+            return Err("Output Connection Closed".to_string());
         }
+        Ok(())
     }
-
-    Ok(())
 }
 
 /// Function to handle crawling a single page.
+/// The depth is the number of links between the seed url and the page.
+/// Pages with the same text as an earlier page are linked to it and not searched or expanded.
+/// Pages not modified since the last crawl get their links and word hits from the last crawl.
 /// Is Single Threaded.
-async fn crawl_page(
-    url: Url,
-    client: reqwest::Client,
-    tx: mpsc::Sender<Link>,
-    tx_selenium: mpsc::Sender<String>,
-    limit: usize,
-    resolver: trust_dns_resolver::TokioAsyncResolver,
-    word_list: Arc<HashSet<String>>,
-) {
+async fn crawl_page(url: Url, depth: usize, context: CrawlContext, tx: mpsc::Sender<Link>) {
     let mut link = links::Link::new_from_url(&url);
-    let resp = match get_page(url.as_str(), &client).await {
+    link.depth = depth;
+    if let Some(Err(e)) = link
+        .host
+        .as_ref()
+        .map(|x| context.resolver.policy().check_host(x))
+    {
+        link.crawled = true;
+        link.refused = Some(e.0);
+        let _ = tx.send(link).await;
+        return;
+    }
+    let headers = context.recrawl.conditional_headers(&url);
+    let cache = context.http_cache.as_ref();
+    let resp = match get_page(url.as_str(), &context.client, headers, cache).await {
         Ok(x) => x,
-        Err(_) => {
+        Err(e) => {
             link.crawled = true;
+            link.refused = policy::find_refusal(&e).map(|x| x.0);
+            link.status = e.status().map(|x| x.as_u16());
+            if link.refused.is_none() && context.triggers.fires(&link, depth, false, false) {
+                let _ = context.tx_selenium.send(link.clone()).await;
+            }
             let _ = tx.send(link.clone()).await;
 
             return;
         }
     };
     link.update_from_response(&resp);
-    if let Some(host) = &link.host {
-        let host = host.to_string();
-        let ipv4 = links::resolve_ipv4(&resolver, &host).await;
-        let ipv6 = links::resolve_ipv6(&resolver, &host).await;
-        link.update_dns(ipv4, ipv6);
+    if let (Some(host), true) = (&link.host, context.dns_lookups) {
+        context.host_cache.resolve(&context.resolver, host).await;
     };
-    let is_html = link.check_mime_from_list(&[mime::TEXT_HTML, mime::TEXT_HTML_UTF_8]);
+    let content_type = link.content_type.clone();
+    let kind = content_type
+        .as_ref()
+        .and_then(content::ContentKind::from_mime);
+    let mut record = recrawl::PageRecord::new(Some(resp.headers()));
 
-    if is_html {
-        let html = match resp.text().await {
-            Ok(x) => x,
-            Err(_) => {
-                return;
+    let (word_hits, links, changed) = if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        reuse_page(&url, &mut record, &context)
+    } else if let (Some(kind), Some(content_type)) = (kind, content_type) {
+        let page = extract_page(&mut link, &mut record, resp, kind, &content_type, &context);
+        match page.await {
+            Some(x) => x,
+            None => return,
+        }
+    } else {
+        if let Some(mirror) = &context.mirror {
+            if mirror.accepts(link.content_type.as_ref()) {
+                if let Ok(body) = content::read_body(resp, context.max_body_size).await {
+                    link.truncated = body.truncated;
+                    context.budget.add_bytes(body.bytes.len());
+                    save_body(&link, &body, mirror).await;
+                }
             }
-        };
-        if links::check_words_html(&html, word_list) {
-            link.contains_words = true;
-            let _ = tx_selenium.send(link.url.to_string()).await;
         }
+        link.recrawl = context.recrawl.record(&url, record);
+        if context.triggers.fires(&link, depth, false, false) {
+            let _ = context.tx_selenium.send(link.clone()).await;
+        }
+        let _ = tx.send(link).await;
+        return;
+    };
+    record.links = links.iter().map(|x| x.url.clone()).collect();
+    record.links.sort();
+    record.word_hits = word_hits.clone();
+    link.recrawl = context.recrawl.record(&url, record);
+    link.word_hits = word_hits;
+    link.contains_words = !link.word_hits.is_empty();
+    link.matched_rules = context.rules.evaluate(&link.word_hits);
+    let flagged = is_flagged(&link, &context.rules);
+    if context.triggers.fires(&link, depth, flagged, changed) {
+        let _ = context.tx_selenium.send(link.clone()).await;
+    }
 
-        let links = links::get_links_from_html(&html, url.as_str());
-        let tx_ref = &tx;
-        stream::iter(links)
-            .for_each_concurrent(limit, |x| async move {
-                let _ = tx_ref.send(x).await;
-            })
-            .await;
+    let tx_ref = &tx;
+    let context_ref = &context;
+    stream::iter(links)
+        .for_each_concurrent(context.task_limit, |mut x| async move {
+            x.depth = depth + 1;
+            if context_ref.dns_lookups {
+                x.mail_domain_valid = context_ref
+                    .mail_cache
+                    .validate_all(&context_ref.resolver, &x.mail_domains())
+                    .await;
+            }
+            let _ = tx_ref.send(x).await;
+        })
+        .await;
+
+    let _ = tx.send(link).await;
+}
+
+/// Function to read and extract a page, returning its word hits, its links
+/// and if its content changed since the last crawl.
+/// The hash of the content is kept in the record of the page.
+/// Returns None if the body could not be read.
+async fn extract_page(
+    link: &mut Link,
+    record: &mut recrawl::PageRecord,
+    resp: reqwest::Response,
+    kind: content::ContentKind,
+    content_type: &mime::Mime,
+    context: &CrawlContext,
+) -> Option<(Vec<words::WordHit>, HashSet<Link>, bool)> {
+    let url = link.url.clone();
+    let body = content::read_body(resp, context.max_body_size).await.ok()?;
+    link.truncated = body.truncated;
+    context.budget.add_bytes(body.bytes.len());
+    if let Some(mirror) = &context.mirror {
+        save_body(link, &body, mirror).await;
     }
+    let hash = content::fnv1a(&body.bytes);
+    record.content_hash = Some(format!("{:016x}", hash));
+    let changed = context.content_hashes.update(url.as_str(), hash);
+    let trapped = context.traps.check_content(&url, &body.bytes).is_some();
+    let content = match content::extract(kind, content_type, body).await {
+        Some(content::Content::Html(x)) => {
+            Some(content::Content::Html(render_html(link, x, context).await))
+        }
+        x => x,
+    };
+    link.duplicate_of = match &content {
+        Some(content::Content::Html(x)) => context.duplicates.add(&url, &words::visible_text(x)),
+        Some(content::Content::Text(x)) => context.duplicates.add(&url, x),
+        None => None,
+    };
+    let (word_hits, links) = match content {
+        _ if link.duplicate_of.is_some() => (Vec::new(), HashSet::new()),
+        Some(content::Content::Html(x)) => (
+            context.word_list.search_html(&x),
+            links::get_links_from_html(&x, url.as_str()),
+        ),
+        Some(content::Content::Text(x)) => (
+            context.word_list.search_text(&x),
+            links::get_links_from_plain_text(&x, url.as_str()),
+        ),
+        None => (Vec::new(), HashSet::new()),
+    };
+    let links = if trapped { HashSet::new() } else { links };
+    Some((word_hits, links, changed))
+}
 
-    if let Err(_) = tx.send(link).await {
-        return;
+/// Function to get the word hits and the links of a page which was not modified
+/// since the last crawl, from the last crawl. Keeps the validators of the last crawl
+/// which the response did not send again.
+fn reuse_page(
+    url: &Url,
+    record: &mut recrawl::PageRecord,
+    context: &CrawlContext,
+) -> (Vec<words::WordHit>, HashSet<Link>, bool) {
+    let previous = context.recrawl.previous(url).cloned().unwrap_or_default();
+    record.etag = record.etag.take().or(previous.etag);
+    record.last_modified = record.last_modified.take().or(previous.last_modified);
+    record.content_hash = previous.content_hash;
+    context.content_hashes.keep(url.as_str());
+    if let Some(mirror) = &context.mirror {
+        mirror.keep(url, MirrorEntry::new(Some(304), None, false));
+    }
+    let links = previous.links.iter().map(Link::new_from_url).collect();
+    (previous.word_hits, links, false)
+}
+
+/// Function to save the body of a page into the mirror.
+async fn save_body(link: &Link, body: &content::Body, mirror: &Mirror) {
+    let entry = MirrorEntry::new(link.status, link.headers(), body.truncated);
+    let mime = link.content_type.as_ref();
+    if let Err(e) = mirror.save(&link.url, mime, entry, &body.bytes).await {
+        println!("Error : Could not save {} in the mirror : {}", link.url, e);
+    }
+}
+
+/// Function to get the html of a page as rendered by the browser, if it should be rendered.
+/// Keeps the static html if rendering fails.
+async fn render_html(link: &mut Link, html: String, context: &CrawlContext) -> String {
+    let render = match &context.render {
+        Some(x) => x,
+        None => return html,
+    };
+    let url = link.url.as_str();
+    if !render.should_render(&html, url, &context.word_list, &context.rules) {
+        return html;
+    }
+    match render.renderer.render(url).await {
+        Ok(x) => {
+            link.rendered = true;
+            x
+        }
+        Err(_) => html,
+    }
+}
+
+/// Function to check if a page should be flagged for review.
+/// Pages are flagged by the rules if there are any, else by any search word.
+fn is_flagged(link: &Link, rules: &rules::RuleSet) -> bool {
+    if rules.is_empty() {
+        link.contains_words
+    } else {
+        !link.matched_rules.is_empty()
     }
 }
 
 /// Function to find and crawl sitemaps from robottxt.
-async fn crawl_sitemaps(url: Url, tx: mpsc::Sender<Link>, limit: usize, client: reqwest::Client) {
+/// Sitemaps listed in sitemap indexes are crawled too, up to MAX_SITEMAPS in total.
+async fn crawl_sitemaps(
+    url: Url,
+    tx: mpsc::Sender<Link>,
+    limit: usize,
+    client: reqwest::Client,
+    cache: Option<HttpCache>,
+) {
     let mut robottxt_url = url.clone();
     robottxt_url.set_path("robots.txt");
-    let robottxt = match get_page(robottxt_url.as_str(), &client).await {
+    let robottxt = match get_page(
+        robottxt_url.as_str(),
+        &client,
+        Default::default(),
+        cache.as_ref(),
+    )
+    .await
+    {
         Ok(x) => match x.text().await {
             Ok(x) => x,
             Err(_) => return,
@@ -262,55 +614,649 @@ async fn crawl_sitemaps(url: Url, tx: mpsc::Sender<Link>, limit: usize, client:
         Err(_) => return,
     };
     let url_str = url.to_string();
-    robottxt
+    let mut sitemaps: VecDeque<Url> = robottxt
         .lines()
         .filter(|x| x.contains("Sitemap"))
         .filter_map(|x| x[9..].split_whitespace().next())
         .map(|x| x.trim())
         .filter_map(|x| links::normalize_url(x, &url_str))
-        .for_each(|x| {
-            let tx_clone = tx.clone();
-            let client_clone = client.clone();
-            let limit_clone = limit.clone();
-            tokio::spawn(async move {
-                crawl_sitemap(x.url, tx_clone, limit_clone, client_clone).await;
-            });
-        });
+        .map(|x| x.url)
+        .collect();
+    let mut crawled = HashSet::new();
+    while let Some(x) = sitemaps.pop_front() {
+        if crawled.len() >= MAX_SITEMAPS {
+            break;
+        }
+        if crawled.insert(x.clone()) {
+            sitemaps.extend(crawl_sitemap(x, &tx, limit, &client, cache.as_ref()).await);
+        }
+    }
 }
 
 /// Function to crawl a single sitemap.
-/// Currently only supports text sitemap
-async fn crawl_sitemap(url: Url, tx: mpsc::Sender<Link>, limit: usize, client: reqwest::Client) {
+/// Supports text and xml sitemaps, and returns the sitemaps listed in a sitemap index.
+async fn crawl_sitemap(
+    url: Url,
+    tx: &mpsc::Sender<Link>,
+    limit: usize,
+    client: &reqwest::Client,
+    cache: Option<&HttpCache>,
+) -> Vec<Url> {
     let mut link = links::Link::new_from_url(&url);
-    let resp = match get_page(url.as_str(), &client).await {
+    let resp = match get_page(url.as_str(), client, Default::default(), cache).await {
         Ok(x) => x,
-        Err(_) => return,
+        Err(_) => return Vec::new(),
     };
     link.update_from_response(&resp);
     let text = match resp.text().await {
         Ok(x) => x,
-        Err(_) => return,
+        Err(_) => return Vec::new(),
     };
-    let links = match link.content_type {
+    let (links, sitemaps) = match link.content_type {
+        Some(x) if x.subtype() == mime::XML || x.suffix() == Some(mime::XML) => {
+            links::get_links_from_sitemap(&text, url.as_str())
+        }
         Some(x) => match (x.type_(), x.subtype()) {
-            (mime::TEXT, mime::PLAIN) => links::get_links_from_text(&text, url.as_str()),
-            _ => return,
+            (mime::TEXT, mime::PLAIN) => {
+                (links::get_links_from_text(&text, url.as_str()), Vec::new())
+            }
+            _ => return Vec::new(),
         },
-        None => return,
+        None => return Vec::new(),
     };
-    let tx_ref = &tx;
     stream::iter(links)
-        .for_each_concurrent(limit, |x| async {
-            let _ = tx_ref.send(x).await;
+        .for_each_concurrent(limit, |mut x| async {
+            x.depth = 1;
+            let _ = tx.send(x).await;
         })
         .await;
+    sitemaps.into_iter().map(|x| x.url).collect()
 }
 
-/// Funtion to perform a get request on a url
+/// Funtion to perform a get request on a url, with extra headers.
+/// Answers from the http cache if there is one and it has the response.
 async fn get_page(
     url: &str,
     client: &reqwest::Client,
+    headers: reqwest::header::HeaderMap,
+    cache: Option<&HttpCache>,
 ) -> Result<reqwest::Response, reqwest::Error> {
-    let resp = client.get(url).send().await?;
+    if let Some(x) = cache {
+        if let Some(resp) = x.get(url).await {
+            return resp.error_for_status();
+        }
+    }
+    let resp = client.get(url).headers(headers).send().await?;
+    let resp = match cache {
+        Some(x) => x.put(url, resp).await?,
+        None => resp,
+    };
     resp.error_for_status()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_cache::CacheMode;
+    use crate::policy::NetworkPolicy;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// State of the local test site.
+    /// Requests are logged by path when they arrive and when they are answered,
+    /// and the most pages served at the same time is kept.
+    #[derive(Debug, Default)]
+    pub struct SiteState {
+        pub requests: Vec<String>,
+        pub served: Vec<String>,
+        active: usize,
+        pub max_active: usize,
+    }
+
+    pub type Site = Arc<std::sync::Mutex<SiteState>>;
+
+    /// Local http site answering every request after 50 milliseconds.
+    /// Pages are html bodies by path, or images for paths ending in `.png`,
+    /// other paths are not found.
+    /// Paths containing `slow` are answered after 500 milliseconds.
+    /// Returns the url of the site and its state.
+    pub async fn spawn_site(pages: HashMap<String, String>) -> (String, Site) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let site = Site::default();
+        let site_clone = site.clone();
+        let pages = Arc::new(pages);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, pages.clone(), site_clone.clone()));
+            }
+        });
+        (format!("http://{}", addr), site)
+    }
+
+    /// Function to answer the requests of one keep-alive connection.
+    async fn serve_connection(stream: TcpStream, pages: Arc<HashMap<String, String>>, site: Site) {
+        let mut stream = BufReader::new(stream);
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let path = line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_string();
+            let mut if_none_match = None;
+            loop {
+                let mut header = String::new();
+                if stream.read_line(&mut header).await.unwrap_or(0) == 0 {
+                    return;
+                }
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("if-none-match") {
+                        if_none_match = Some(value.trim().to_string());
+                    }
+                }
+            }
+
+            {
+                let mut site = site.lock().unwrap();
+                site.requests.push(path.clone());
+                site.active += 1;
+                site.max_active = site.max_active.max(site.active);
+            }
+            let delay = if path.contains("slow") { 500 } else { 50 };
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            {
+                let mut site = site.lock().unwrap();
+                site.served.push(path.clone());
+                site.active -= 1;
+            }
+
+            let etag = pages
+                .get(&path)
+                .map(|x| format!("\"{:x}\"", content::fnv1a(x.as_bytes())));
+            let response = match pages.get(&path) {
+                Some(_) if etag.is_some() && if_none_match == etag => format!(
+                    "HTTP/1.1 304 Not Modified\r\nETag: {}\r\nContent-Length: 0\r\n\r\n",
+                    etag.unwrap_or_default()
+                ),
+                Some(x) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nETag: {}\r\nContent-Length: {}\r\n\r\n{}",
+                    if path.ends_with(".png") { "image/png" } else { "text/html" },
+                    etag.unwrap_or_default(),
+                    x.len(),
+                    x
+                ),
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+            };
+            if stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Function to get the options for crawling the local test site.
+    pub fn site_options(task_limit: usize) -> CrawlOptions {
+        let mut whitelist = HashSet::new();
+        whitelist.insert(url::Host::parse("127.0.0.1").unwrap());
+        CrawlOptions {
+            whitelist: Some(whitelist),
+            blacklist: None,
+            word_list: words::SearchTerms::new(Vec::new(), &words::SearchOptions::default())
+                .unwrap(),
+            rules: rules::RuleSet::default(),
+            task_limit,
+            timeout: 5,
+            max_body_size: 1 << 20,
+            host_cache: dns::HostCache::new(false),
+            resolver: dns::ResolverOptions {
+                nameservers: vec!["127.0.0.1:1".parse().unwrap()],
+                overrides: HashMap::new(),
+                policy: NetworkPolicy::new(false, &[]),
+            },
+            dns_lookups: false,
+            render: None,
+            triggers: triggers::Triggers::default(),
+            content_hashes: triggers::ContentHashes::default(),
+            strategy: Strategy::default(),
+            budget: budget::BudgetTracker::default(),
+            store: Store::memory(),
+            traps: traps::TrapDetector::default(),
+            duplicates: dedup::Duplicates::default(),
+            recrawl: recrawl::Recrawl::default(),
+            http_cache: None,
+            mirror: None,
+        }
+    }
+
+    /// Function to get a site of a start page linking to the given number of pages.
+    fn wide_site(width: usize) -> HashMap<String, String> {
+        let mut pages = HashMap::new();
+        let links: String = (0..width)
+            .map(|x| format!(r#"<a href="/{}">{}</a>"#, x, x))
+            .collect();
+        pages.insert(
+            "/".to_string(),
+            format!("<html><body>{}</body></html>", links),
+        );
+        for x in 0..width {
+            pages.insert(format!("/{}", x), "<html></html>".to_string());
+        }
+        pages
+    }
+
+    /// Function to crawl the local test site, returning the crawled and the other urls output.
+    pub async fn crawl_site(
+        url: &str,
+        depth: Option<usize>,
+        options: CrawlOptions,
+    ) -> (Vec<String>, Vec<String>) {
+        let (tx_output, mut rx_output) = mpsc::channel::<Link>(1);
+        let (tx_selenium, _rx_selenium) = mpsc::channel(1);
+        let output = tokio::spawn(async move {
+            let (mut crawled, mut other) = (Vec::new(), Vec::new());
+            while let Some(x) = rx_output.recv().await {
+                let path = x.url.path().to_string();
+                if x.crawled {
+                    crawled.push(path);
+                } else {
+                    other.push(path);
+                }
+            }
+            (crawled, other)
+        });
+        let origin = Link::new_from_str(url).unwrap();
+        crawl(origin, depth, false, options, tx_output, tx_selenium)
+            .await
+            .unwrap();
+        output.await.unwrap()
+    }
+
+    /// Function to get a site from the links of every page.
+    pub fn site(pages: &[(&str, &[&str])]) -> HashMap<String, String> {
+        pages
+            .iter()
+            .map(|(path, links)| {
+                let links: String = links
+                    .iter()
+                    .map(|x| format!(r#"<a href="{}">{}</a>"#, x, x))
+                    .collect();
+                (
+                    path.to_string(),
+                    format!("<html><body>{}</body></html>", links),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn streaming_frontier() {
+        let pages = site(&[
+            ("/", &["/slow", "/a"]),
+            ("/slow", &["/d"]),
+            ("/a", &["/b"]),
+            ("/b", &["/c", "/a"]),
+            ("/c", &["/d"]),
+            ("/d", &[]),
+        ]);
+        let (url, site) = spawn_site(pages).await;
+        let (mut crawled, mut other) = crawl_site(&url, Some(3), site_options(2)).await;
+        crawled.sort();
+        other.sort();
+
+        assert_eq!(crawled, vec!["/", "/a", "/b", "/d", "/slow"]);
+        assert_eq!(other, vec!["/c"]);
+        let site = site.lock().unwrap();
+        assert_eq!(site.served, vec!["/", "/a", "/b", "/slow", "/d"]);
+    }
+
+    #[tokio::test]
+    async fn disk_frontier() {
+        let (url, site) = spawn_site(wide_site(20)).await;
+        let path = std::env::temp_dir().join(format!("crate-crawl-{}", std::process::id()));
+        let mut options = site_options(3);
+        options.store = Store::open(&path, Some(100)).unwrap();
+        let (crawled, other) = crawl_site(&url, Some(2), options).await;
+        let _ = std::fs::remove_dir_all(&path);
+
+        assert_eq!(crawled.len(), 21);
+        assert!(other.is_empty());
+        assert_eq!(site.lock().unwrap().requests.len(), 21);
+    }
+
+    #[tokio::test]
+    async fn crawler_traps() {
+        let mut pages = HashMap::new();
+        for x in 0..30 {
+            pages.insert(
+                format!("/cal/{}", x),
+                format!(
+                    r#"<html><body>Day {} <a href="/cal/{}">next</a></body></html>"#,
+                    x,
+                    x + 1
+                ),
+            );
+        }
+        pages.insert(
+            "/".to_string(),
+            r#"<html><a href="/cal/0">calendar</a><a href="/a/a/a/a">a</a></html>"#.to_string(),
+        );
+        let (url, site) = spawn_site(pages).await;
+        let mut options = site_options(2);
+        options.traps = traps::TrapDetector::new(traps::TrapLimits {
+            max_segment_repeats: Some(3),
+            max_similar_pages: Some(3),
+            ..traps::TrapLimits::default()
+        });
+        let traps = options.traps.clone();
+        let (crawled, mut other) = crawl_site(&url, None, options).await;
+        other.sort();
+
+        assert_eq!(crawled.len(), 5);
+        assert_eq!(other, vec!["/a/a/a/a"]);
+        assert_eq!(site.lock().unwrap().requests.len(), 5);
+        assert_eq!(traps.cuts().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn duplicate_pages() {
+        let mut pages = site(&[("/", &["/a", "/b"]), ("/x", &[]), ("/y", &[])]);
+        pages.insert(
+            "/a".to_string(),
+            r#"<html>Same page <a href="/x">x</a></html>"#.to_string(),
+        );
+        pages.insert(
+            "/b".to_string(),
+            r#"<html>Same   page <a href="/y">x</a></html>"#.to_string(),
+        );
+        let (url, site) = spawn_site(pages).await;
+        let options = site_options(1);
+        let duplicates = options.duplicates.clone();
+        let (crawled, other) = crawl_site(&url, None, options).await;
+
+        assert_eq!(crawled.len(), 4);
+        assert!(other.is_empty());
+        assert_eq!(site.lock().unwrap().requests.len(), 4);
+        assert_eq!(duplicates.report().exact.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn conditional_recrawl() {
+        let (url, _) = spawn_site(site(&[
+            ("/", &["/a", "/b"]),
+            ("/a", &[]),
+            ("/b", &["/x"]),
+            ("/x", &[]),
+        ]))
+        .await;
+        let mut options = site_options(2);
+        options.recrawl = recrawl::Recrawl::new(recrawl::PageFile::default(), "s".to_string());
+        let recrawl = options.recrawl.clone();
+        let (crawled, _) = crawl_site(&url, None, options).await;
+        assert_eq!(crawled.len(), 4);
+        let first_url = url;
+        // The second site has another port, so the urls of the first crawl are moved to it.
+        let previous = recrawl.pages();
+        let moved = |from: &str, to: &str| recrawl::PageFile {
+            settings: previous.settings.clone(),
+            pages: previous
+                .pages
+                .iter()
+                .map(|(x, record)| {
+                    let mut record = record.clone();
+                    for link in record.links.iter_mut() {
+                        *link = Url::parse(&link.as_str().replace(from, to)).unwrap();
+                    }
+                    (x.replace(from, to), record)
+                })
+                .collect(),
+        };
+
+        let mut pages = site(&[("/", &["/a", "/b"]), ("/b", &["/x"]), ("/n", &[])]);
+        pages.insert(
+            "/a".to_string(),
+            r#"<html>Changed <a href="/n">n</a></html>"#.to_string(),
+        );
+        let (url, site) = spawn_site(pages).await;
+        let mut options = site_options(2);
+        options.recrawl = recrawl::Recrawl::new(moved(&first_url, &url), "s".to_string());
+        let recrawl = options.recrawl.clone();
+        let (mut crawled, _) = crawl_site(&url, None, options).await;
+        crawled.sort();
+
+        assert_eq!(crawled, vec!["/", "/a", "/b", "/n", "/x"]);
+        let report = recrawl.report();
+        let paths =
+            |x: Vec<String>| -> Vec<String> { x.iter().map(|x| x.replace(&url, "")).collect() };
+        assert_eq!(paths(report.unchanged), vec!["/", "/b"]);
+        assert_eq!(paths(report.changed), vec!["/a"]);
+        assert_eq!(paths(report.new), vec!["/n"]);
+        assert_eq!(paths(report.gone), vec!["/x"]);
+        assert_eq!(site.lock().unwrap().requests.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn offline_replay() {
+        let (url, site) =
+            spawn_site(site(&[("/", &["/a", "/b"]), ("/a", &["/b"]), ("/b", &[])])).await;
+        let folder = std::env::temp_dir().join(format!("crate-http-cache-{}", std::process::id()));
+        let cache = |mode| HttpCache::new(folder.clone(), mode, Duration::from_secs(60), 1 << 20);
+
+        let mut options = site_options(2);
+        options.http_cache = Some(cache(CacheMode::Normal));
+        let (mut crawled, _) = crawl_site(&url, None, options).await;
+        crawled.sort();
+        let requests = site.lock().unwrap().requests.len();
+
+        let mut options = site_options(2);
+        options.http_cache = Some(cache(CacheMode::Normal));
+        let (mut cached, _) = crawl_site(&url, None, options).await;
+        cached.sort();
+        assert_eq!(cached, crawled);
+
+        let mut options = site_options(2);
+        options.http_cache = Some(cache(CacheMode::Offline));
+        let (mut offline, _) = crawl_site(&url, None, options).await;
+        offline.sort();
+        assert_eq!(offline, crawled);
+
+        let other_url = url.replace("127.0.0.1", "localhost");
+        let mut options = site_options(2);
+        options.http_cache = Some(cache(CacheMode::Offline));
+        options.whitelist = None;
+        let (missed, _) = crawl_site(&other_url, None, options).await;
+        let _ = std::fs::remove_dir_all(&folder);
+
+        assert_eq!(crawled, vec!["/", "/a", "/b"]);
+        assert_eq!(missed, vec!["/"]);
+        assert_eq!(site.lock().unwrap().requests.len(), requests);
+    }
+
+    #[tokio::test]
+    async fn archive_pages() {
+        let (url, _) = spawn_site(site(&[("/", &["/docs"]), ("/docs", &["/", "/missing"])])).await;
+        let folder = std::env::temp_dir().join(format!("crate-mirror-{}", std::process::id()));
+        let mirror = Mirror::new(folder.clone(), Vec::new());
+        let mut options = site_options(2);
+        options.mirror = Some(mirror.clone());
+        crawl_site(&url, None, options).await;
+        let saved = mirror.finish().await.unwrap();
+
+        let host = url.trim_start_matches("http://").replace(':', "_");
+        let read = |x: &str| std::fs::read_to_string(folder.join(x)).unwrap_or_default();
+        let index = read(&format!("{}/index.html", host));
+        let docs = read(&format!("{}/docs/index.html", host));
+        let manifest = read("manifest.json");
+        let _ = std::fs::remove_dir_all(&folder);
+
+        assert_eq!(saved, 2);
+        assert!(index.contains(r#"href="docs/index.html""#));
+        assert!(docs.contains(r#"href="../index.html""#));
+        assert!(docs.contains(r#"href="/missing""#));
+        assert!(manifest.contains(&format!("{}/docs/index.html", host)));
+    }
+
+    #[tokio::test]
+    async fn crawl_budget() {
+        let (url, site) = spawn_site(wide_site(20)).await;
+        let mut options = site_options(2);
+        options.budget = budget::BudgetTracker::new(budget::Budget {
+            max_pages: Some(5),
+            ..budget::Budget::default()
+        });
+        let tracker = options.budget.clone();
+        let (crawled, other) = crawl_site(&url, None, options).await;
+
+        assert_eq!(crawled.len(), 5);
+        assert_eq!(other.len(), 16);
+        assert_eq!(tracker.report().stopped_by, Some(budget::Limit::Pages));
+        assert_eq!(site.lock().unwrap().requests.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn crawl_deadline() {
+        let (url, _) = spawn_site(site(&[("/", &["/slow"]), ("/slow", &[])])).await;
+        let mut options = site_options(2);
+        options.budget = budget::BudgetTracker::new(budget::Budget {
+            max_duration: Some(Duration::from_millis(200)),
+            ..budget::Budget::default()
+        });
+        let tracker = options.budget.clone();
+        crawl_site(&url, None, options).await;
+
+        assert_eq!(tracker.report().stopped_by, Some(budget::Limit::Duration));
+    }
+
+    #[tokio::test]
+    async fn trigger_unextracted_pages() {
+        let pages = site(&[("/", &["/logo.png", "/a"]), ("/logo.png", &[]), ("/a", &[])]);
+        let (url, _) = spawn_site(pages).await;
+        let options = CrawlOptions {
+            triggers: triggers::Triggers::new(vec![r"url:\.png$".parse().unwrap()]),
+            ..site_options(2)
+        };
+        let (tx_output, mut rx_output) = mpsc::channel::<Link>(1);
+        let (tx_selenium, mut rx_selenium) = mpsc::channel(10);
+        let output = tokio::spawn(async move { while rx_output.recv().await.is_some() {} });
+        let origin = Link::new_from_str(&url).unwrap();
+        crawl(origin, None, false, options, tx_output, tx_selenium)
+            .await
+            .unwrap();
+        output.await.unwrap();
+
+        let screenshot = rx_selenium.recv().await.unwrap();
+        assert_eq!(screenshot.url.path(), "/logo.png");
+        assert_eq!(screenshot.content_type, Some(mime::IMAGE_PNG));
+        assert!(rx_selenium.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn sitemaps_beside_workers() {
+        let mut pages = site(&[("/", &["/a"]), ("/a", &[]), ("/slow-sitemap", &[])]);
+        pages.insert(
+            "/robots.txt".to_string(),
+            "Sitemap: /slow-sitemap".to_string(),
+        );
+        let (url, site) = spawn_site(pages).await;
+        let (tx_output, mut rx_output) = mpsc::channel::<Link>(1);
+        let (tx_selenium, _rx_selenium) = mpsc::channel(1);
+        let output = tokio::spawn(async move { while rx_output.recv().await.is_some() {} });
+        let origin = Link::new_from_str(&url).unwrap();
+        crawl(origin, None, true, site_options(1), tx_output, tx_selenium)
+            .await
+            .unwrap();
+        output.await.unwrap();
+
+        let site = site.lock().unwrap();
+        let served = |x: &str| site.served.iter().position(|y| y == x);
+        assert!(served("/a") < served("/slow-sitemap"));
+        assert!(served("/slow-sitemap").is_some());
+    }
+
+    #[tokio::test]
+    async fn bounded_workers() {
+        let (url, site) = spawn_site(wide_site(20)).await;
+        let (crawled, _) = crawl_site(&url, Some(2), site_options(3)).await;
+
+        assert_eq!(crawled.len(), 21);
+        let site = site.lock().unwrap();
+        assert_eq!(site.requests.len(), 21);
+        assert_eq!(site.max_active, 3);
+    }
+
+    #[tokio::test]
+    async fn refuse_private_hosts() {
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            "internal.test".to_string(),
+            vec!["127.0.0.1".parse().unwrap()],
+        );
+        let resolver = dns::Resolver::new(&dns::ResolverOptions {
+            nameservers: vec!["127.0.0.1:1".parse().unwrap()],
+            overrides,
+            policy: NetworkPolicy::new(true, &[]),
+        })
+        .unwrap();
+        let client = init_reqwest_client(1, &resolver).unwrap();
+
+        let e = get_page(
+            "http://internal.test/admin",
+            &client,
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            policy::find_refusal(&e).map(|x| x.0),
+            Some("Refused loopback address 127.0.0.1".to_string())
+        );
+    }
+
+    #[test]
+    fn render_decision() {
+        let renderer = selenium::Renderer::new(
+            selenium::tests::default_options("http://127.0.0.1:1".to_string()),
+            1,
+        );
+        let rules = rules::RuleSet::new(vec![r#"spa: "Loading""#.to_string()]).unwrap();
+        let word_list =
+            words::SearchTerms::new(rules.terms(), &words::SearchOptions::default()).unwrap();
+        let url = "https://example.com/";
+        let shell = r#"<html><body><div id="root">Loading</div></body></html>"#;
+        let page = r#"<html><body><a href="/a">a</a><a href="/b">b</a></body></html>"#;
+
+        let render = RenderOptions {
+            renderer,
+            rules: Vec::new(),
+            min_links: None,
+        };
+        assert!(render.should_render(page, url, &word_list, &rules));
+
+        let render = RenderOptions {
+            min_links: Some(2),
+            ..render
+        };
+        assert!(render.should_render(shell, url, &word_list, &rules));
+        assert!(!render.should_render(page, url, &word_list, &rules));
+
+        let render = RenderOptions {
+            rules: vec!["spa".to_string()],
+            min_links: None,
+            ..render
+        };
+        assert!(render.should_render(shell, url, &word_list, &rules));
+        assert!(!render.should_render(page, url, &word_list, &rules));
+    }
+}
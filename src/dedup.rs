@@ -0,0 +1,278 @@
+/*!
+Module containing the detection of exact and near-duplicate pages.
+*/
+use crate::extractors::content::fnv1a;
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Number of words in a shingle.
+const SHINGLE_SIZE: usize = 3;
+/// Number of hashes in a MinHash.
+const MINHASH_SIZE: usize = 64;
+/// Number of bits in a band of the SimHash. Pages in the same cluster share a band.
+const SIMHASH_BAND: u32 = 16;
+/// Number of later pages of a band a page is compared to, so large bands stay fast.
+const MAX_BAND_CANDIDATES: usize = 100;
+/// Minimum similarity of near-duplicates by default.
+const DEFAULT_THRESHOLD: f64 = 0.9;
+
+/// Fingerprints of the text of a page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fingerprint {
+    pub simhash: u64,
+    pub minhash: Vec<u64>,
+}
+
+impl Fingerprint {
+    /// Creates a new Fingerprint of normalized text.
+    pub fn new(text: &str) -> Self {
+        let words: Vec<&str> = text.split(' ').collect();
+        let shingles: Vec<u64> = words
+            .windows(SHINGLE_SIZE.min(words.len()))
+            .map(|x| fnv1a(x.join(" ").as_bytes()))
+            .collect();
+
+        let mut weights = [0i64; 64];
+        for x in &shingles {
+            for (bit, weight) in weights.iter_mut().enumerate() {
+                if x >> bit & 1 == 1 {
+                    *weight += 1;
+                } else {
+                    *weight -= 1;
+                }
+            }
+        }
+        let simhash = weights
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x > 0)
+            .fold(0, |hash, (bit, _)| hash | 1 << bit);
+
+        let minhash = (0..MINHASH_SIZE as u64)
+            .map(|seed| {
+                shingles
+                    .iter()
+                    .map(|x| mix(x ^ seed.wrapping_mul(0x9e3779b97f4a7c15)))
+                    .min()
+                    .unwrap_or(u64::MAX)
+            })
+            .collect();
+        Fingerprint { simhash, minhash }
+    }
+
+    /// Function to estimate the Jaccard similarity of the shingles of two pages.
+    pub fn similarity(&self, other: &Fingerprint) -> f64 {
+        let same = self
+            .minhash
+            .iter()
+            .zip(&other.minhash)
+            .filter(|(x, y)| x == y)
+            .count();
+        same as f64 / MINHASH_SIZE as f64
+    }
+}
+
+/// Function to scramble the bits of a hash, giving another hash function for every seed.
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51afd7ed558ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ceb9fe1a85ec53);
+    x ^ x >> 33
+}
+
+/// Function to normalize the text of a page, lowercased and with the whitespace collapsed.
+pub fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .map(|x| x.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Near-duplicate pages, with the lowest similarity between a page and the first one.
+#[derive(Clone, Debug, Serialize)]
+pub struct Cluster {
+    pub urls: Vec<String>,
+    pub similarity: f64,
+}
+
+/// Duplicates found in a crawl, written at the end of it.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DuplicateReport {
+    pub exact: BTreeMap<String, Vec<String>>,
+    pub near: Vec<Cluster>,
+}
+
+/// Pages seen so far.
+#[derive(Debug, Default)]
+struct Pages {
+    first_seen: HashMap<u64, Url>,
+    exact: BTreeMap<String, Vec<String>>,
+    fingerprints: Vec<(Url, Fingerprint)>,
+}
+
+/// Detector of duplicate pages.
+#[derive(Clone, Debug)]
+pub struct Duplicates {
+    threshold: f64,
+    pages: Arc<Mutex<Pages>>,
+}
+
+impl Default for Duplicates {
+    fn default() -> Self {
+        Duplicates::new(DEFAULT_THRESHOLD)
+    }
+}
+
+impl Duplicates {
+    /// Creates a new Duplicates, with the minimum similarity of near-duplicates.
+    pub fn new(threshold: f64) -> Self {
+        Duplicates {
+            threshold,
+            pages: Arc::default(),
+        }
+    }
+
+    /// Function to add the text of a page.
+    /// Returns the first url seen with the same text, if it is an exact duplicate.
+    pub fn add(&self, url: &Url, text: &str) -> Option<Url> {
+        let text = normalize(text);
+        if text.is_empty() {
+            return None;
+        }
+        let hash = fnv1a(text.as_bytes());
+        {
+            let mut pages = self.pages.lock().ok()?;
+            if let Some(first) = pages.first_seen.get(&hash).cloned() {
+                if first == *url {
+                    return None;
+                }
+                pages
+                    .exact
+                    .entry(first.to_string())
+                    .or_default()
+                    .push(url.to_string());
+                return Some(first);
+            }
+            pages.first_seen.insert(hash, url.clone());
+        }
+        let fingerprint = Fingerprint::new(&text);
+        if let Ok(mut x) = self.pages.lock() {
+            x.fingerprints.push((url.clone(), fingerprint));
+        }
+        None
+    }
+
+    /// Function to get the exact duplicates and the clusters of near-duplicates so far.
+    pub fn report(&self) -> DuplicateReport {
+        let pages = match self.pages.lock() {
+            Ok(x) => x,
+            Err(_) => return DuplicateReport::default(),
+        };
+        let fingerprints = &pages.fingerprints;
+
+        let mut parents: Vec<usize> = (0..fingerprints.len()).collect();
+        fn root(parents: &mut [usize], mut x: usize) -> usize {
+            while parents[x] != x {
+                parents[x] = parents[parents[x]];
+                x = parents[x];
+            }
+            x
+        }
+        let mut bands: HashMap<(u32, u64), Vec<usize>> = HashMap::new();
+        for (i, (_, x)) in fingerprints.iter().enumerate() {
+            for band in 0..64 / SIMHASH_BAND {
+                let bits = x.simhash >> (band * SIMHASH_BAND) & ((1 << SIMHASH_BAND) - 1);
+                bands.entry((band, bits)).or_default().push(i);
+            }
+        }
+        for candidates in bands.values() {
+            for (n, &x) in candidates.iter().enumerate() {
+                for &y in candidates[n + 1..].iter().take(MAX_BAND_CANDIDATES) {
+                    let (root_x, root_y) = (root(&mut parents, x), root(&mut parents, y));
+                    if root_x != root_y
+                        && fingerprints[x].1.similarity(&fingerprints[y].1) >= self.threshold
+                    {
+                        parents[root_x.max(root_y)] = root_x.min(root_y);
+                    }
+                }
+            }
+        }
+
+        let mut clusters: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for x in 0..fingerprints.len() {
+            clusters.entry(root(&mut parents, x)).or_default().push(x);
+        }
+        let near = clusters
+            .into_iter()
+            .filter(|(_, x)| x.len() > 1)
+            .map(|(first, x)| Cluster {
+                similarity: x
+                    .iter()
+                    .map(|y| fingerprints[first].1.similarity(&fingerprints[*y].1))
+                    .fold(1.0, f64::min),
+                urls: x.iter().map(|y| fingerprints[*y].0.to_string()).collect(),
+            })
+            .collect();
+        DuplicateReport {
+            exact: pages.exact.clone(),
+            near,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(x: &str) -> Url {
+        Url::parse(&format!("https://example.com/{}", x)).unwrap()
+    }
+
+    #[test]
+    fn fingerprints() {
+        let text: Vec<String> = (0..200).map(|x| format!("word{}", x)).collect();
+        let text = text.join(" ");
+        let near = text.replace("word100 ", "other ");
+        let other: Vec<String> = (0..200).map(|x| format!("thing{}", x)).collect();
+
+        let a = Fingerprint::new(&text);
+        let b = Fingerprint::new(&near);
+        let c = Fingerprint::new(&other.join(" "));
+        assert!(a.similarity(&b) > 0.8);
+        assert!(a.similarity(&c) < 0.2);
+        assert!((a.simhash ^ b.simhash).count_ones() < (a.simhash ^ c.simhash).count_ones());
+        assert_eq!(Fingerprint::new("a b"), Fingerprint::new("a b"));
+    }
+
+    #[test]
+    fn duplicate_pages() {
+        let duplicates = Duplicates::new(0.8);
+        let text: Vec<String> = (0..200).map(|x| format!("word{}", x)).collect();
+        let text = text.join(" ");
+
+        assert_eq!(duplicates.add(&url("a"), &text), None);
+        assert_eq!(duplicates.add(&url("a"), &text), None);
+        assert_eq!(
+            duplicates.add(&url("b"), &format!("  {}\n", text.to_uppercase())),
+            Some(url("a"))
+        );
+        assert_eq!(
+            duplicates.add(&url("c"), &text.replace("word100", "other")),
+            None
+        );
+        assert_eq!(duplicates.add(&url("d"), "something else entirely"), None);
+        assert_eq!(duplicates.add(&url("e"), " "), None);
+
+        let report = duplicates.report();
+        assert_eq!(report.exact[url("a").as_str()], vec![url("b").to_string()]);
+        assert_eq!(report.near.len(), 1);
+        assert_eq!(
+            report.near[0].urls,
+            vec![url("a").to_string(), url("c").to_string()]
+        );
+        assert!(report.near[0].similarity >= 0.8);
+    }
+}
@@ -0,0 +1,602 @@
+/*!
+Module containing DNS related functions.
+*/
+use crate::policy::NetworkPolicy;
+use futures::future;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use trust_dns_resolver::config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::proto::rr::{RData, RecordType};
+use trust_dns_resolver::proto::xfer::DnsRequestOptions;
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// Maximum number of CNAME records followed for a host.
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// Upstream nameserver for the resolver.
+/// Parsed from `[udp|tcp|tls|https://]ip[:port][#tls-name]`.
+/// A nameserver without protocol is queried over both UDP and TCP.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameServer {
+    addr: SocketAddr,
+    protocols: Vec<Protocol>,
+    tls_dns_name: Option<String>,
+}
+
+impl FromStr for NameServer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (protocols, default_port, rest) = match s.split_once("://") {
+            None => (vec![Protocol::Udp, Protocol::Tcp], 53, s),
+            Some(("udp", x)) => (vec![Protocol::Udp], 53, x),
+            Some(("tcp", x)) => (vec![Protocol::Tcp], 53, x),
+            Some(("tls", x)) => (vec![Protocol::Tls], 853, x),
+            Some(("https", x)) => (vec![Protocol::Https], 443, x),
+            Some((x, _)) => return Err(format!("Unknown nameserver protocol {}", x)),
+        };
+        let (addr, tls_dns_name) = match rest.split_once('#') {
+            Some((addr, name)) => (addr, Some(name.to_string())),
+            None => (rest, None),
+        };
+        let addr = match addr.parse::<SocketAddr>() {
+            Ok(x) => x,
+            Err(_) => match addr.parse::<IpAddr>() {
+                Ok(x) => SocketAddr::new(x, default_port),
+                Err(_) => return Err(format!("Invalid nameserver address {}", addr)),
+            },
+        };
+        let encrypted = protocols.iter().any(|x| x.is_encrypted());
+        if encrypted && tls_dns_name.is_none() {
+            return Err(
+                "Encrypted nameservers need a name, like tls://1.1.1.1#cloudflare-dns.com"
+                    .to_string(),
+            );
+        }
+        Ok(NameServer {
+            addr,
+            protocols,
+            tls_dns_name,
+        })
+    }
+}
+
+impl NameServer {
+    /// Function to get the resolver configuration of the nameserver.
+    fn configs(&self) -> Vec<NameServerConfig> {
+        self.protocols
+            .iter()
+            .map(|x| NameServerConfig {
+                socket_addr: self.addr,
+                protocol: *x,
+                tls_dns_name: self.tls_dns_name.clone(),
+                trust_nx_responses: true,
+                tls_config: None,
+            })
+            .collect()
+    }
+}
+
+/// Options for the DNS resolver.
+/// The system configuration is used if no nameservers are given.
+#[derive(Clone, Debug, Default)]
+pub struct ResolverOptions {
+    pub nameservers: Vec<NameServer>,
+    pub overrides: HashMap<String, Vec<IpAddr>>,
+    pub policy: NetworkPolicy,
+}
+
+/// DNS resolver shared between the http client and the DNS lookups.
+/// Answers from the overrides before asking the nameservers.
+/// The http client can only connect to the addresses allowed by the network policy.
+#[derive(Clone)]
+pub struct Resolver {
+    inner: TokioAsyncResolver,
+    overrides: Arc<HashMap<String, Vec<IpAddr>>>,
+    policy: Arc<NetworkPolicy>,
+}
+
+impl Resolver {
+    /// Creates a new Resolver from the options.
+    pub fn new(options: &ResolverOptions) -> Result<Self, String> {
+        let inner = if options.nameservers.is_empty() {
+            TokioAsyncResolver::tokio_from_system_conf()
+        } else {
+            let nameservers: Vec<NameServerConfig> = options
+                .nameservers
+                .iter()
+                .flat_map(|x| x.configs())
+                .collect();
+            let config = ResolverConfig::from_parts(None, vec![], nameservers);
+            TokioAsyncResolver::tokio(config, ResolverOpts::default())
+        };
+        match inner {
+            Ok(x) => Ok(Resolver {
+                policy: Arc::new(options.policy.clone()),
+                ..Resolver::with_resolver(x, options.overrides.clone())
+            }),
+            Err(_) => Err("Could not build dns resolver".to_string()),
+        }
+    }
+
+    /// Creates a new Resolver from an existing trust-dns resolver.
+    fn with_resolver(inner: TokioAsyncResolver, overrides: HashMap<String, Vec<IpAddr>>) -> Self {
+        let overrides = overrides
+            .into_iter()
+            .map(|(k, v)| (normalize_name(&k), v))
+            .collect();
+        Resolver {
+            inner,
+            overrides: Arc::new(overrides),
+            policy: Arc::new(NetworkPolicy::default()),
+        }
+    }
+
+    /// Function to get the network policy.
+    pub fn policy(&self) -> &NetworkPolicy {
+        &self.policy
+    }
+
+    /// Function to get the overridden addresses of a host.
+    fn get_override(&self, host: &str) -> Option<&Vec<IpAddr>> {
+        self.overrides.get(&normalize_name(host))
+    }
+
+    /// Function to get all the addresses of a host.
+    /// Honours the overrides.
+    pub async fn lookup_ip(&self, host: &str) -> Vec<IpAddr> {
+        if let Some(x) = self.get_override(host) {
+            return x.clone();
+        }
+        match self.inner.lookup_ip(host).await {
+            Ok(x) => x.iter().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl reqwest::dns::Resolve for Resolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let ips = resolver.lookup_ip(name.as_str()).await;
+            if ips.is_empty() {
+                return Err(format!("Could not resolve {}", name.as_str()).into());
+            }
+            let ips = resolver.policy.filter_ips(ips)?;
+            let addrs: reqwest::dns::Addrs =
+                Box::new(ips.into_iter().map(|x| SocketAddr::new(x, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+/// Function to normalize a host name for comparison.
+fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+/// DNS information collected for a host.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cname: Vec<String>,
+    pub ipv4: Vec<Ipv4Addr>,
+    pub ipv6: Vec<Ipv6Addr>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ptr: BTreeMap<IpAddr, Vec<String>>,
+}
+
+/// Cache for host DNS information.
+/// Every host is only resolved once, no matter how many pages are crawled on it.
+#[derive(Clone, Default)]
+pub struct HostCache {
+    hosts: Arc<Mutex<HashMap<String, Option<HostInfo>>>>,
+    reverse: bool,
+}
+
+impl HostCache {
+    /// Creates a new HostCache.
+    /// Also performs reverse (PTR) lookups for every address if reverse is set.
+    pub fn new(reverse: bool) -> Self {
+        HostCache {
+            reverse,
+            ..HostCache::default()
+        }
+    }
+
+    /// Function to resolve a host if it has not been resolved yet.
+    pub async fn resolve(&self, resolver: &Resolver, host: &url::Host) {
+        let name = host.to_string();
+        match self.hosts.lock() {
+            Ok(mut x) => {
+                if x.contains_key(&name) {
+                    return;
+                }
+                x.insert(name.clone(), None);
+            }
+            Err(_) => return,
+        }
+
+        let mut info = match host {
+            url::Host::Domain(x) => lookup_domain(resolver, x).await,
+            url::Host::Ipv4(x) => HostInfo {
+                ipv4: vec![*x],
+                ..HostInfo::default()
+            },
+            url::Host::Ipv6(x) => HostInfo {
+                ipv6: vec![*x],
+                ..HostInfo::default()
+            },
+        };
+        info.host = name.clone();
+        if self.reverse {
+            info.ptr = reverse_lookup_all(resolver, &info).await;
+        }
+
+        if let Ok(mut x) = self.hosts.lock() {
+            x.insert(name, Some(info));
+        }
+    }
+
+    /// Function to get the information of all the resolved hosts.
+    /// Sorted by the host name.
+    pub fn hosts(&self) -> Vec<HostInfo> {
+        let mut hosts: Vec<HostInfo> = match self.hosts.lock() {
+            Ok(x) => x.values().flatten().cloned().collect(),
+            Err(_) => Vec::new(),
+        };
+        hosts.sort_by(|a, b| a.host.cmp(&b.host));
+        hosts
+    }
+}
+
+/// Function to resolve all the addresses and the CNAME chain of a domain.
+/// The lookups are run concurrently.
+async fn lookup_domain(resolver: &Resolver, domain: &str) -> HostInfo {
+    if let Some(x) = resolver.get_override(domain) {
+        return HostInfo {
+            ipv4: x
+                .iter()
+                .filter_map(|x| match x {
+                    IpAddr::V4(x) => Some(*x),
+                    IpAddr::V6(_) => None,
+                })
+                .collect(),
+            ipv6: x
+                .iter()
+                .filter_map(|x| match x {
+                    IpAddr::V4(_) => None,
+                    IpAddr::V6(x) => Some(*x),
+                })
+                .collect(),
+            ..HostInfo::default()
+        };
+    }
+    let (ipv4, ipv6, cname) = future::join3(
+        resolve_ipv4(resolver, domain),
+        resolve_ipv6(resolver, domain),
+        resolve_cname_chain(resolver, domain),
+    )
+    .await;
+    HostInfo {
+        ipv4,
+        ipv6,
+        cname,
+        ..HostInfo::default()
+    }
+}
+
+/// Function to get IPV4 DNS
+pub async fn resolve_ipv4(resolver: &Resolver, query: &str) -> Vec<Ipv4Addr> {
+    match resolver.inner.ipv4_lookup(query).await {
+        Ok(x) => x.iter().copied().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Function to get IPV6 DNS
+pub async fn resolve_ipv6(resolver: &Resolver, query: &str) -> Vec<Ipv6Addr> {
+    match resolver.inner.ipv6_lookup(query).await {
+        Ok(x) => x.iter().copied().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Function to follow the CNAME chain of a domain.
+/// Stops after MAX_CNAME_CHAIN records to avoid loops.
+pub async fn resolve_cname_chain(resolver: &Resolver, query: &str) -> Vec<String> {
+    let mut chain: Vec<String> = Vec::new();
+    let mut name = query.to_string();
+    while chain.len() < MAX_CNAME_CHAIN {
+        let lookup = match resolver
+            .inner
+            .lookup(
+                name.as_str(),
+                RecordType::CNAME,
+                DnsRequestOptions::default(),
+            )
+            .await
+        {
+            Ok(x) => x,
+            Err(_) => break,
+        };
+        let target = match lookup.iter().find_map(|x| match x {
+            RData::CNAME(x) => Some(x.to_utf8()),
+            _ => None,
+        }) {
+            Some(x) => x,
+            None => break,
+        };
+        if chain.contains(&target) {
+            break;
+        }
+        chain.push(target.clone());
+        name = target;
+    }
+    chain
+}
+
+/// Function to get the PTR records of all the addresses of a host.
+async fn reverse_lookup_all(resolver: &Resolver, info: &HostInfo) -> BTreeMap<IpAddr, Vec<String>> {
+    let ips = info
+        .ipv4
+        .iter()
+        .map(|x| IpAddr::V4(*x))
+        .chain(info.ipv6.iter().map(|x| IpAddr::V6(*x)));
+    future::join_all(ips.map(|ip| async move {
+        let names: Vec<String> = match resolver.inner.reverse_lookup(ip).await {
+            Ok(x) => x.iter().map(|x| x.to_utf8()).collect(),
+            Err(_) => Vec::new(),
+        };
+        (ip, names)
+    }))
+    .await
+    .into_iter()
+    .filter(|(_, names)| !names.is_empty())
+    .collect()
+}
+
+/// Cache for mail domain validation.
+/// Shared between the crawl tasks so that every domain is only looked up once.
+#[derive(Clone, Default)]
+pub struct MailDomainCache {
+    domains: Arc<Mutex<HashMap<String, bool>>>,
+}
+
+impl MailDomainCache {
+    /// Function to check if a domain can receive mails.
+    /// A domain is valid if it has a MX record, or an A/AAAA record to fall back to.
+    pub async fn validate(&self, resolver: &Resolver, domain: &str) -> bool {
+        if let Some(x) = self.get(domain) {
+            return x;
+        }
+        let valid = has_mx_record(resolver, domain).await || has_ip_record(resolver, domain).await;
+        if let Ok(mut domains) = self.domains.lock() {
+            domains.insert(domain.to_string(), valid);
+        }
+        valid
+    }
+
+    /// Function to check if all the recipients of a mail link have valid domains.
+    /// Returns None if the link does not contain any domain.
+    pub async fn validate_all(&self, resolver: &Resolver, domains: &[String]) -> Option<bool> {
+        if domains.is_empty() {
+            return None;
+        }
+        for domain in domains {
+            if !self.validate(resolver, domain).await {
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+
+    /// Function to get a cached result.
+    fn get(&self, domain: &str) -> Option<bool> {
+        match self.domains.lock() {
+            Ok(x) => x.get(domain).copied(),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Function to check if a domain has a MX record.
+/// A null MX record (RFC 7505) means the domain does not accept mails.
+async fn has_mx_record(resolver: &Resolver, domain: &str) -> bool {
+    match resolver.inner.mx_lookup(domain).await {
+        Ok(x) => x.iter().any(|x| !x.exchange().is_root()),
+        Err(_) => false,
+    }
+}
+
+/// Function to check if a domain has an A or AAAA record.
+async fn has_ip_record(resolver: &Resolver, domain: &str) -> bool {
+    !resolver.lookup_ip(domain).await.is_empty()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use tokio::net::UdpSocket;
+    use trust_dns_resolver::config::NameServerConfigGroup;
+    use trust_dns_resolver::proto::op::{Message, MessageType, ResponseCode};
+    use trust_dns_resolver::proto::rr::{rdata::MX, Name, RData, Record};
+
+    /// Local DNS stand-in.
+    /// Answers with the records given for a name and with NXDOMAIN for everything else.
+    pub async fn spawn_dns_server(records: Vec<Record>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                let request = match Message::from_vec(&buf[..len]) {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                let mut response = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(request.recursion_desired())
+                    .set_recursion_available(true);
+                response.add_queries(request.queries().to_vec());
+                let answers: Vec<Record> = request
+                    .queries()
+                    .iter()
+                    .flat_map(|q| {
+                        records.iter().filter(move |r| {
+                            r.name() == q.name() && r.record_type() == q.query_type()
+                        })
+                    })
+                    .cloned()
+                    .collect();
+                let known = request
+                    .queries()
+                    .iter()
+                    .any(|q| records.iter().any(|r| r.name() == q.name()));
+                if !known {
+                    response.set_response_code(ResponseCode::NXDomain);
+                }
+                response.add_answers(answers);
+                if let Ok(x) = response.to_vec() {
+                    let _ = socket.send_to(&x, peer).await;
+                }
+            }
+        });
+        addr
+    }
+
+    /// Function to build a resolver which only talks to the given server.
+    pub fn test_resolver(addr: SocketAddr) -> Resolver {
+        let config = ResolverConfig::from_parts(
+            None,
+            vec![],
+            NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true),
+        );
+        let opts = ResolverOpts {
+            cache_size: 0,
+            attempts: 1,
+            use_hosts_file: false,
+            ..ResolverOpts::default()
+        };
+        Resolver::with_resolver(
+            TokioAsyncResolver::tokio(config, opts).unwrap(),
+            HashMap::new(),
+        )
+    }
+
+    pub fn record(name: &str, rdata: RData) -> Record {
+        let name = Name::from_ascii(name).unwrap();
+        let record_type = rdata.to_record_type();
+        let mut record = Record::with(name, record_type, 60);
+        record.set_rdata(rdata);
+        record
+    }
+
+    #[tokio::test]
+    async fn mail_domain_validation() {
+        let addr = spawn_dns_server(vec![
+            record(
+                "mail.test.",
+                RData::MX(MX::new(10, Name::from_ascii("mx.mail.test.").unwrap())),
+            ),
+            record("web.test.", RData::A(Ipv4Addr::new(10, 0, 0, 1))),
+            record("null.test.", RData::MX(MX::new(0, Name::root()))),
+        ])
+        .await;
+        let resolver = test_resolver(addr);
+        let cache = MailDomainCache::default();
+
+        assert!(cache.validate(&resolver, "mail.test").await);
+        assert!(cache.validate(&resolver, "web.test").await);
+        assert!(!cache.validate(&resolver, "null.test").await);
+        assert!(!cache.validate(&resolver, "dead.test").await);
+        assert_eq!(cache.get("dead.test"), Some(false));
+
+        let domains = vec!["mail.test".to_string(), "dead.test".to_string()];
+        assert_eq!(cache.validate_all(&resolver, &domains).await, Some(false));
+        assert_eq!(cache.validate_all(&resolver, &[]).await, None);
+    }
+
+    #[tokio::test]
+    async fn host_resolution() {
+        let addr = spawn_dns_server(vec![
+            record(
+                "www.site.test.",
+                RData::CNAME(Name::from_ascii("cdn.site.test.").unwrap()),
+            ),
+            record(
+                "cdn.site.test.",
+                RData::CNAME(Name::from_ascii("edge.site.test.").unwrap()),
+            ),
+            record("edge.site.test.", RData::A(Ipv4Addr::new(10, 0, 0, 1))),
+            record("edge.site.test.", RData::A(Ipv4Addr::new(10, 0, 0, 2))),
+        ])
+        .await;
+        let resolver = test_resolver(addr);
+
+        let chain = resolve_cname_chain(&resolver, "www.site.test.").await;
+        assert_eq!(chain, vec!["cdn.site.test.", "edge.site.test."]);
+
+        let cache = HostCache::new(false);
+        let host = url::Host::Domain("edge.site.test.".to_string());
+        cache.resolve(&resolver, &host).await;
+        cache.resolve(&resolver, &host).await;
+        let hosts = cache.hosts();
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].ipv4.len(), 2);
+        assert!(hosts[0].ipv6.is_empty());
+    }
+
+    #[test]
+    fn parse_nameservers() {
+        let x: NameServer = "9.9.9.9".parse().unwrap();
+        assert_eq!(x.addr, "9.9.9.9:53".parse().unwrap());
+        assert_eq!(x.protocols, vec![Protocol::Udp, Protocol::Tcp]);
+
+        let x: NameServer = "https://1.1.1.1#cloudflare-dns.com".parse().unwrap();
+        assert_eq!(x.addr, "1.1.1.1:443".parse().unwrap());
+        assert_eq!(x.protocols, vec![Protocol::Https]);
+        assert_eq!(x.tls_dns_name, Some("cloudflare-dns.com".to_string()));
+
+        let x: NameServer = "tcp://[::1]:5353".parse().unwrap();
+        assert_eq!(x.addr, "[::1]:5353".parse().unwrap());
+
+        assert!("tls://1.1.1.1".parse::<NameServer>().is_err());
+        assert!("quic://1.1.1.1".parse::<NameServer>().is_err());
+        assert!("localhost".parse::<NameServer>().is_err());
+    }
+
+    #[tokio::test]
+    async fn resolver_overrides() {
+        let addr = spawn_dns_server(vec![record(
+            "www.site.test.",
+            RData::A(Ipv4Addr::new(10, 0, 0, 1)),
+        )])
+        .await;
+        let resolver = test_resolver(addr);
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            "Staging.Site.Test".to_string(),
+            vec!["192.168.0.10".parse().unwrap()],
+        );
+        let resolver = Resolver::with_resolver(resolver.inner, overrides);
+
+        assert_eq!(
+            resolver.lookup_ip("staging.site.test").await,
+            vec!["192.168.0.10".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(
+            resolver.lookup_ip("www.site.test.").await,
+            vec!["10.0.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert!(resolver.lookup_ip("dead.site.test.").await.is_empty());
+    }
+}
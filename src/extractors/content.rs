@@ -0,0 +1,194 @@
+//! Submodule containing functions related to the content of a page.
+//! Extracts the text of html, pdf, plain text, json and javascript bodies.
+use mime::Mime;
+
+/// Kind of content the crawler can extract text from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContentKind {
+    Html,
+    Pdf,
+    Text,
+    Json,
+    JavaScript,
+}
+
+impl ContentKind {
+    /// Function to get the ContentKind from a mime type.
+    /// Returns None for content which cannot be searched.
+    pub fn from_mime(mime: &Mime) -> Option<Self> {
+        let subtype = mime.subtype().as_str();
+        let suffix = mime.suffix().map(|x| x.as_str());
+        match (mime.type_(), subtype) {
+            (mime::TEXT, "html") => Some(ContentKind::Html),
+            (mime::APPLICATION, "xhtml") => Some(ContentKind::Html),
+            (mime::APPLICATION, "pdf") => Some(ContentKind::Pdf),
+            (mime::APPLICATION, "json") => Some(ContentKind::Json),
+            (mime::APPLICATION, "javascript") | (mime::APPLICATION, "x-javascript") => {
+                Some(ContentKind::JavaScript)
+            }
+            (mime::TEXT, "javascript") => Some(ContentKind::JavaScript),
+            (mime::APPLICATION, _) if suffix == Some("json") => Some(ContentKind::Json),
+            (mime::TEXT, _) => Some(ContentKind::Text),
+            _ => None,
+        }
+    }
+}
+
+/// Extracted content of a page.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Content {
+    Html(String),
+    Text(String),
+}
+
+/// Body of a http response.
+/// Truncated if it was bigger than the size limit.
+#[derive(Clone, Debug, Default)]
+pub struct Body {
+    pub bytes: Vec<u8>,
+    pub truncated: bool,
+}
+
+/// Function to read the body of a response up to a size limit.
+/// Bigger bodies are read up to the limit and truncated there.
+pub async fn read_body(mut resp: reqwest::Response, limit: usize) -> Result<Body, reqwest::Error> {
+    let mut body = Body::default();
+    while let Some(chunk) = resp.chunk().await? {
+        let left = limit - body.bytes.len();
+        if chunk.len() > left {
+            body.bytes.extend_from_slice(&chunk[..left]);
+            body.truncated = true;
+            break;
+        }
+        body.bytes.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Function to extract the content of a body.
+/// Pdfs are extracted on a blocking thread since it is cpu heavy.
+/// Truncated pdfs cannot be read, so nothing is extracted from them.
+pub async fn extract(kind: ContentKind, mime: &Mime, body: Body) -> Option<Content> {
+    match kind {
+        ContentKind::Html => Some(Content::Html(decode(mime, &body.bytes))),
+        ContentKind::Text | ContentKind::JavaScript => {
+            Some(Content::Text(decode(mime, &body.bytes)))
+        }
+        ContentKind::Json => Some(Content::Text(json_text(&decode(mime, &body.bytes)))),
+        ContentKind::Pdf => {
+            if body.truncated {
+                return None;
+            }
+            let text = tokio::task::spawn_blocking(move || pdf_text(&body.bytes)).await;
+            match text {
+                Ok(Some(x)) => Some(Content::Text(x)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Function to hash bytes with 64 bit FNV-1a.
+/// Unlike the hasher of the standard library it is stable, so hashes are the same in every run.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, x| {
+        (hash ^ *x as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Function to decode a body using the charset from the mime type.
+/// Falls back to UTF-8.
+fn decode(mime: &Mime, bytes: &[u8]) -> String {
+    let encoding = mime
+        .get_param(mime::CHARSET)
+        .and_then(|x| encoding_rs::Encoding::for_label(x.as_str().as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    let (text, _, _) = encoding.decode(bytes);
+    text.into_owned()
+}
+
+/// Function to get all the strings from a json document, one per line.
+/// Returns the raw text if it is not valid json, which happens for truncated bodies.
+fn json_text(json: &str) -> String {
+    fn collect(value: &serde_json::Value, text: &mut String) {
+        match value {
+            serde_json::Value::String(x) => {
+                text.push_str(x);
+                text.push('\n');
+            }
+            serde_json::Value::Array(x) => x.iter().for_each(|x| collect(x, text)),
+            serde_json::Value::Object(x) => x.values().for_each(|x| collect(x, text)),
+            _ => {}
+        }
+    }
+
+    match serde_json::from_str::<serde_json::Value>(json) {
+        Ok(x) => {
+            let mut text = String::new();
+            collect(&x, &mut text);
+            text
+        }
+        Err(_) => json.to_string(),
+    }
+}
+
+/// Function to extract the text of a pdf.
+/// The pdf library panics on some malformed files, so panics are caught.
+fn pdf_text(bytes: &[u8]) -> Option<String> {
+    match std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes)) {
+        Ok(Ok(x)) => Some(x),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_kinds() {
+        let kind = |x: &str| ContentKind::from_mime(&x.parse().unwrap());
+
+        assert_eq!(kind("text/html; charset=utf-8"), Some(ContentKind::Html));
+        assert_eq!(kind("application/pdf"), Some(ContentKind::Pdf));
+        assert_eq!(kind("text/plain"), Some(ContentKind::Text));
+        assert_eq!(kind("application/ld+json"), Some(ContentKind::Json));
+        assert_eq!(kind("text/javascript"), Some(ContentKind::JavaScript));
+        assert_eq!(kind("image/png"), None);
+    }
+
+    #[tokio::test]
+    async fn extract_json() {
+        let mime: Mime = "application/json".parse().unwrap();
+        let body = Body {
+            bytes: br#"{"contact": {"mail": "a@b.com"}, "pages": ["https://x.com/", 1]}"#.to_vec(),
+            truncated: false,
+        };
+        assert_eq!(
+            extract(ContentKind::Json, &mime, body).await,
+            Some(Content::Text("a@b.com\nhttps://x.com/\n".to_string()))
+        );
+
+        let mime: Mime = "text/plain; charset=iso-8859-1".parse().unwrap();
+        let body = Body {
+            bytes: b"caf\xe9".to_vec(),
+            truncated: false,
+        };
+        assert_eq!(
+            extract(ContentKind::Text, &mime, body).await,
+            Some(Content::Text("café".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn read_truncated_body() {
+        let resp = reqwest::Response::from(hyper::http::Response::new(b"abcdef".to_vec()));
+        assert_eq!(resp.content_length(), Some(6));
+        let body = read_body(resp, 4).await.unwrap();
+        assert_eq!(body.bytes, b"abcd");
+        assert!(body.truncated);
+
+        let resp = reqwest::Response::from(hyper::http::Response::new(b"abcdef".to_vec()));
+        assert!(!read_body(resp, 6).await.unwrap().truncated);
+    }
+}
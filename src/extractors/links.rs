@@ -3,23 +3,20 @@ use mime::Mime;
 use reqwest::Url;
 use select::{document::Document, predicate::Name};
 use serde::{Deserialize, Serialize};
-use std::{
-    collections::HashSet, fmt, hash::Hash, hash::Hasher, net::Ipv4Addr, net::Ipv6Addr, sync::Arc,
-};
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+use super::words::WordHit;
+use crate::recrawl::PageStatus;
+use regex::Regex;
+use std::{collections::HashSet, fmt, hash::Hash, hash::Hasher, sync::OnceLock};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 pub enum LinkType {
     Mail,
     PhoneNo,
+    #[default]
     Other,
 }
 
-impl std::default::Default for LinkType {
-    fn default() -> Self {
-        Self::Other
-    }
-}
-
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Link {
     pub url: Url,
@@ -31,12 +28,30 @@ pub struct Link {
     headers: Option<reqwest::header::HeaderMap>,
     #[serde(skip)]
     pub crawled: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    ipv4: Option<Ipv4Addr>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    ipv6: Option<Ipv6Addr>,
+    #[serde(skip)]
+    pub depth: usize,
+    #[serde(skip)]
+    pub sitemap_priority: Option<f64>,
     pub link_type: LinkType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
     pub contains_words: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub word_hits: Vec<WordHit>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matched_rules: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mail_domain_valid: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refused: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rendered: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<Url>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recrawl: Option<PageStatus>,
 }
 
 impl Link {
@@ -45,16 +60,11 @@ impl Link {
     pub fn new(
         url: &Url,
         headers: &Option<reqwest::header::HeaderMap>,
-        ipv4: Option<Ipv4Addr>,
-        ipv6: Option<Ipv6Addr>,
         crawled: bool,
         link_type: LinkType,
         contains_words: bool,
     ) -> Self {
-        let host = match url.host() {
-            Some(x) => Some(x.to_owned()),
-            None => None,
-        };
+        let host = url.host().map(|x| x.to_owned());
         let content_type = match headers {
             Some(x) => Self::get_mime(x),
             None => None,
@@ -64,11 +74,20 @@ impl Link {
             headers: headers.to_owned(),
             content_type,
             host,
-            ipv4,
-            ipv6,
             crawled,
+            depth: 0,
+            sitemap_priority: None,
             link_type,
             contains_words,
+            word_hits: Vec::new(),
+            matched_rules: Vec::new(),
+            mail_domain_valid: None,
+            refused: None,
+            truncated: false,
+            rendered: false,
+            duplicate_of: None,
+            recrawl: None,
+            status: None,
         }
     }
 
@@ -82,8 +101,6 @@ impl Link {
         Some(Self::new(
             &parsed_url,
             &None,
-            None,
-            None,
             false,
             Self::get_link_type(&parsed_url),
             false,
@@ -93,15 +110,7 @@ impl Link {
     /// Creates a new Link form Url url.
     /// Assumes other things
     pub fn new_from_url(url: &Url) -> Self {
-        Self::new(
-            url,
-            &None,
-            None,
-            None,
-            false,
-            Self::get_link_type(url),
-            false,
-        )
+        Self::new(url, &None, false, Self::get_link_type(url), false)
     }
 
     /// Creates a new Link if realtive url is supplied.
@@ -142,19 +151,19 @@ impl Link {
         }
     }
 
-    /// Function to update ipv4 and ipv6 dns.
-    pub fn update_dns(&mut self, ipv4: Option<Ipv4Addr>, ipv6: Option<Ipv6Addr>) {
-        self.ipv4 = ipv4;
-        self.ipv6 = ipv6;
-    }
-
     /// Function to updated Link from http response.
     pub fn update_from_response(&mut self, response: &reqwest::Response) {
         self.content_type = Self::get_mime(response.headers());
         self.headers = Some(response.headers().to_owned());
+        self.status = Some(response.status().as_u16());
         self.crawled = true;
     }
 
+    /// Function to get the headers of the response, if the page was crawled.
+    pub fn headers(&self) -> Option<&reqwest::header::HeaderMap> {
+        self.headers.as_ref()
+    }
+
     /// Function to get page mime type from http header.
     fn get_mime(header: &reqwest::header::HeaderMap) -> Option<Mime> {
         let mime_str = header.get(reqwest::header::CONTENT_TYPE)?.to_str();
@@ -167,12 +176,20 @@ impl Link {
         None
     }
 
-    /// Function to check if the mime is present in a list.
-    pub fn check_mime_from_list(&self, required_mime: &[Mime]) -> bool {
-        if let Some(c) = &self.content_type {
-            return required_mime.iter().any(|x| x == c);
+    /// Function to get the domains of the recipients of a mail link.
+    /// Returns an empty list for other link types.
+    pub fn mail_domains(&self) -> Vec<String> {
+        if let LinkType::Mail = self.link_type {
+            percent_encoding::percent_decode_str(self.url.path())
+                .decode_utf8_lossy()
+                .split(',')
+                .filter_map(|x| x.rsplit_once('@'))
+                .map(|(_, domain)| domain.trim().trim_end_matches('.').to_lowercase())
+                .filter(|x| !x.is_empty())
+                .collect()
+        } else {
+            Vec::new()
         }
-        false
     }
 
     /// Function to get the LinkType
@@ -281,71 +298,187 @@ pub fn get_links_from_text(text: &str, url: &str) -> HashSet<Link> {
         .collect()
 }
 
-/// Function to check if a word from a list is present in a page.
-pub fn check_words_html(html: &str, word_list: Arc<HashSet<String>>) -> bool {
-    word_list
-        .iter()
-        .find(|x| html.contains(x.as_str()))
-        .is_some()
+/// Function to get links from a xml sitemap, with their priority.
+/// Also returns the sitemaps listed if it is a sitemap index.
+pub fn get_links_from_sitemap(xml: &str, url: &str) -> (HashSet<Link>, Vec<Link>) {
+    static ENTRY_REGEX: OnceLock<Regex> = OnceLock::new();
+    static LOC_REGEX: OnceLock<Regex> = OnceLock::new();
+    static PRIORITY_REGEX: OnceLock<Regex> = OnceLock::new();
+    let entry_regex = ENTRY_REGEX.get_or_init(|| {
+        Regex::new(r"(?s)<(url|sitemap)>(.*?)</(?:url|sitemap)>").expect("valid entry regex")
+    });
+    let loc_regex = LOC_REGEX
+        .get_or_init(|| Regex::new(r"(?s)<loc>\s*(.*?)\s*</loc>").expect("valid loc regex"));
+    let priority_regex = PRIORITY_REGEX.get_or_init(|| {
+        Regex::new(r"(?s)<priority>\s*(.*?)\s*</priority>").expect("valid priority regex")
+    });
+
+    let mut links = HashSet::new();
+    let mut sitemaps = Vec::new();
+    for entry in entry_regex.captures_iter(xml) {
+        let loc = match loc_regex.captures(&entry[2]) {
+            Some(x) => xml_text(&x[1]),
+            None => continue,
+        };
+        let mut link = match normalize_url(&loc, url) {
+            Some(x) => x,
+            None => continue,
+        };
+        if &entry[1] == "sitemap" {
+            sitemaps.push(link);
+            continue;
+        }
+        link.sitemap_priority = priority_regex
+            .captures(&entry[2])
+            .and_then(|x| x[1].parse().ok());
+        links.insert(link);
+    }
+    (links, sitemaps)
+}
+
+/// Function to get the text of a xml element, with its entities decoded and its CDATA sections unwrapped.
+fn xml_text(raw: &str) -> String {
+    let mut text = String::new();
+    let mut rest = raw;
+    while !rest.is_empty() {
+        if let Some(x) = rest.strip_prefix("<![CDATA[") {
+            let end = x.find("]]>").unwrap_or(x.len());
+            text.push_str(&x[..end]);
+            rest = x.get(end + 3..).unwrap_or_default();
+            continue;
+        }
+        let entity = rest
+            .strip_prefix('&')
+            .and_then(|x| x.split_once(';'))
+            .and_then(|(name, x)| Some((xml_entity(name)?, x)));
+        match entity {
+            Some((c, x)) => {
+                text.push(c);
+                rest = x;
+            }
+            None => {
+                let mut chars = rest.chars();
+                text.extend(chars.next());
+                rest = chars.as_str();
+            }
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Function to decode a xml entity, named or numeric, given without its & and ;.
+fn xml_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "apos" => Some('\''),
+        "quot" => Some('"'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(x) => u32::from_str_radix(x, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Function to get links and mail addresses from free text.
+/// Used for content which is not html, like pdfs, json and javascript.
+/// Only absolute urls are found.
+pub fn get_links_from_plain_text(text: &str, url: &str) -> HashSet<Link> {
+    static URL_REGEX: OnceLock<Regex> = OnceLock::new();
+    static MAIL_REGEX: OnceLock<Regex> = OnceLock::new();
+    let url_regex = URL_REGEX.get_or_init(|| {
+        Regex::new(r#"https?://[^\s"'<>()\[\]{}\\^`|]+"#).expect("valid url regex")
+    });
+    let mail_regex = MAIL_REGEX.get_or_init(|| {
+        Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}")
+            .expect("valid mail regex")
+    });
+
+    let urls = url_regex
+        .find_iter(text)
+        .map(|x| {
+            x.as_str()
+                .trim_end_matches(&['.', ',', ';', ':', '!', '?'][..])
+        })
+        .filter_map(|x| normalize_url(x, url));
+    let mails = mail_regex
+        .find_iter(text)
+        .filter_map(|x| Link::new_from_str(&format!("mailto:{}", x.as_str())));
+    urls.chain(mails).collect()
 }
 
 /// Helper function to parse url in a page.
 /// Converts relative urls to full urls.
 /// Also removes javascript urls and other false urls.
 pub fn normalize_url(url: &str, base_url: &str) -> Option<Link> {
-    if url.starts_with("#") {
+    if url.starts_with('#') {
         // Checks for internal links.
         // Maybe will make it optioanl to ignore them.
         return None;
     }
 
-    match Link::new_from_str(&url) {
+    match Link::new_from_str(url) {
         Some(x) => Some(x),
-        None => Link::new_relative(&url, base_url),
-    }
-}
-
-/// Function to get IPV4 DNS
-pub async fn resolve_ipv4(
-    resolver: &trust_dns_resolver::TokioAsyncResolver,
-    query: &str,
-) -> Option<Ipv4Addr> {
-    match resolver.ipv4_lookup(query).await {
-        Ok(x) => match x.iter().next() {
-            Some(x) => Some(x.to_owned()),
-            None => None,
-        },
-        Err(_) => None,
-    }
-}
-
-/// Function to get IPV6 DNS
-pub async fn resolve_ipv6(
-    resolver: &trust_dns_resolver::TokioAsyncResolver,
-    query: &str,
-) -> Option<Ipv6Addr> {
-    match resolver.ipv6_lookup(query).await {
-        Ok(x) => match x.iter().next() {
-            Some(x) => Some(x.to_owned()),
-            None => None,
-        },
-        Err(_) => None,
+        None => Link::new_relative(url, base_url),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{collections::HashSet, sync::Arc};
 
     #[test]
-    fn get_words() {
-        let html = "This is a sample page which does not work";
-        let mut word_list = HashSet::new();
-
-        assert!(!check_words_html(html, Arc::new(word_list.clone())));
+    fn links_from_sitemap() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://example.com/</loc><priority>1.0</priority></url>
+  <url>
+    <loc>https://example.com/search?q=a&amp;page=2</loc>
+  </url>
+  <url><loc>https://example.com/it&apos;s?a=&quot;b&quot;&#38;c=&#x31;</loc></url>
+  <url><loc><![CDATA[ https://example.com/cdata?a=1&b=2 ]]></loc></url>
+</urlset>"#;
+        let (links, sitemaps) = get_links_from_sitemap(xml, "https://example.com/sitemap.xml");
+        let mut links: Vec<(String, Option<f64>)> = links
+            .into_iter()
+            .map(|x| (x.url.to_string(), x.sitemap_priority))
+            .collect();
+        links.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            links,
+            vec![
+                ("https://example.com/".to_string(), Some(1.0)),
+                ("https://example.com/cdata?a=1&b=2".to_string(), None),
+                ("https://example.com/it's?a=%22b%22&c=1".to_string(), None),
+                ("https://example.com/search?q=a&page=2".to_string(), None)
+            ]
+        );
+        assert!(sitemaps.is_empty());
+
+        let xml = "<sitemapindex><sitemap><loc>/pages.xml</loc></sitemap></sitemapindex>";
+        let (links, sitemaps) = get_links_from_sitemap(xml, "https://example.com/sitemap.xml");
+        assert!(links.is_empty());
+        assert_eq!(sitemaps[0].url.as_str(), "https://example.com/pages.xml");
+    }
 
-        word_list.insert("sample".to_string());
-        assert!(check_words_html(html, Arc::new(word_list)))
+    #[test]
+    fn links_from_plain_text() {
+        let text =
+            "Docs at https://example.com/docs, mail sales@example.co.uk. See (http://x.org/a?b=1).";
+        let links = get_links_from_plain_text(text, "https://example.com/file.pdf");
+        let mut urls: Vec<String> = links.iter().map(|x| x.url.to_string()).collect();
+        urls.sort();
+        assert_eq!(
+            urls,
+            vec![
+                "http://x.org/a?b=1",
+                "https://example.com/docs",
+                "mailto:sales@example.co.uk"
+            ]
+        );
     }
 }
@@ -1,5 +1,8 @@
 /*!
 Module Containg functions related to different Extractors.
-Currently contains only links.
+Contains page content, links, search words and the rules over them.
 */
+pub mod content;
 pub mod links;
+pub mod rules;
+pub mod words;
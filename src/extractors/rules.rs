@@ -0,0 +1,340 @@
+//! Submodule containing boolean rules over the search words.
+//! Rules decide which pages are flagged.
+use super::words::WordHit;
+use std::collections::HashSet;
+
+/// Boolean expression over the search terms.
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Term(String),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    AtLeast(usize, Vec<String>),
+}
+
+impl Expr {
+    /// Function to evaluate the expression against the matched terms.
+    fn eval(&self, matched: &HashSet<&str>) -> bool {
+        match self {
+            Expr::Term(x) => matched.contains(x.as_str()),
+            Expr::And(a, b) => a.eval(matched) && b.eval(matched),
+            Expr::Or(a, b) => a.eval(matched) || b.eval(matched),
+            Expr::Not(a) => !a.eval(matched),
+            Expr::AtLeast(n, terms) => {
+                terms
+                    .iter()
+                    .filter(|x| matched.contains(x.as_str()))
+                    .count()
+                    >= *n
+            }
+        }
+    }
+
+    /// Function to collect all the terms used in the expression.
+    fn terms<'a>(&'a self, terms: &mut Vec<&'a str>) {
+        match self {
+            Expr::Term(x) => terms.push(x),
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.terms(terms);
+                b.terms(terms);
+            }
+            Expr::Not(a) => a.terms(terms),
+            Expr::AtLeast(_, x) => terms.extend(x.iter().map(|x| x.as_str())),
+        }
+    }
+}
+
+/// A named rule.
+#[derive(Clone, Debug)]
+struct Rule {
+    name: String,
+    expr: Expr,
+}
+
+/// List of rules deciding which pages are flagged.
+#[derive(Clone, Debug, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    /// Creates a new RuleSet from the lines of a rules file.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn new<I>(lines: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut rules = Vec::new();
+        for line in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, expr) = match line.split_once(':') {
+                Some((name, expr)) => (name.trim(), expr),
+                None => return Err(format!("Rule without name: {}", line)),
+            };
+            let expr = match parse(expr) {
+                Ok(x) => x,
+                Err(e) => return Err(format!("Invalid rule {}: {}", name, e)),
+            };
+            rules.push(Rule {
+                name: name.to_string(),
+                expr,
+            });
+        }
+        Ok(RuleSet { rules })
+    }
+
+    /// Function to check if there are any rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Function to check if there is a rule with the given name.
+    pub fn has_rule(&self, name: &str) -> bool {
+        self.rules.iter().any(|x| x.name == name)
+    }
+
+    /// Function to get all the search terms used by the rules.
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.rules.iter().for_each(|x| x.expr.terms(&mut terms));
+        terms.into_iter().map(|x| x.to_string()).collect()
+    }
+
+    /// Function to get the names of the rules matching the hits of a page.
+    pub fn evaluate(&self, hits: &[WordHit]) -> Vec<String> {
+        let matched: HashSet<&str> = hits.iter().map(|x| x.term.as_str()).collect();
+        self.rules
+            .iter()
+            .filter(|x| x.expr.eval(&matched))
+            .map(|x| x.name.clone())
+            .collect()
+    }
+}
+
+/// Tokens of a rule expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Term(String),
+    Number(usize),
+    And,
+    Or,
+    Not,
+    AtLeast,
+    Open,
+    Close,
+    Comma,
+}
+
+/// Function to split a rule expression into tokens.
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            '"' => {
+                let mut term = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(x) => term.push(x),
+                            None => return Err("Unterminated term".to_string()),
+                        },
+                        Some(x) => term.push(x),
+                        None => return Err("Unterminated term".to_string()),
+                    }
+                }
+                tokens.push(Token::Term(term));
+            }
+            x if x.is_whitespace() => {}
+            x if x.is_alphanumeric() => {
+                let mut word = x.to_string();
+                while let Some(x) = chars.next_if(|x| x.is_alphanumeric() || *x == '_') {
+                    word.push(x);
+                }
+                let token = match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    "ATLEAST" | "AT_LEAST" => Token::AtLeast,
+                    _ => match word.parse::<usize>() {
+                        Ok(x) => Token::Number(x),
+                        Err(_) => return Err(format!("Unknown word {}", word)),
+                    },
+                };
+                tokens.push(token);
+            }
+            x => return Err(format!("Unexpected character {}", x)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Function to parse a rule expression.
+fn parse(expr: &str) -> Result<Expr, String> {
+    let tokens = tokenize(expr)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(x) => Err(format!("Unexpected {:?}", x)),
+    }
+}
+
+/// Recursive descent parser for rule expressions.
+/// `OR` binds weaker than `AND`, and `NOT` binds the strongest.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.next() {
+            Some(x) if x == token => Ok(()),
+            Some(x) => Err(format!("Expected {:?}, found {:?}", token, x)),
+            None => Err(format!("Expected {:?}", token)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while let Some(Token::Or) = self.peek() {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.pos += 1;
+                    expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+                }
+                Some(Token::Not) => {
+                    expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Term(x)) => Ok(Expr::Term(x)),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            Some(Token::AtLeast) => {
+                self.expect(Token::Open)?;
+                let n = match self.next() {
+                    Some(Token::Number(x)) => x,
+                    _ => return Err("ATLEAST needs a number".to_string()),
+                };
+                let mut terms = Vec::new();
+                while let Some(Token::Comma) = self.peek() {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(Token::Term(x)) => terms.push(x),
+                        _ => return Err("ATLEAST only takes terms".to_string()),
+                    }
+                }
+                self.expect(Token::Close)?;
+                Ok(Expr::AtLeast(n, terms))
+            }
+            Some(x) => Err(format!("Unexpected {:?}", x)),
+            None => Err("Unexpected end of rule".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hits(terms: &[&str]) -> Vec<WordHit> {
+        terms
+            .iter()
+            .map(|x| WordHit {
+                term: x.to_string(),
+                count: 1,
+                snippets: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn evaluate_rules() {
+        let rules = RuleSet::new(vec![
+            "# Credentials in invoices".to_string(),
+            r#"leak: ("invoice" AND "password") NOT "example""#.to_string(),
+            r#"many: ATLEAST(2, "iban", "swift", "/acc(ount)?/i")"#.to_string(),
+            r#"either: "secret" OR NOT "public""#.to_string(),
+        ])
+        .unwrap();
+
+        assert_eq!(rules.terms().len(), 8);
+        assert_eq!(
+            rules.evaluate(&hits(&["invoice", "password", "public"])),
+            vec!["leak"]
+        );
+        assert!(rules
+            .evaluate(&hits(&["invoice", "password", "example", "public"]))
+            .is_empty());
+        assert_eq!(
+            rules.evaluate(&hits(&["swift", "/acc(ount)?/i", "public"])),
+            vec!["many"]
+        );
+        assert_eq!(rules.evaluate(&hits(&[])), vec!["either"]);
+
+        assert_eq!(
+            RuleSet::new(vec![r#"x: "a" AND"#.to_string()]).err(),
+            Some("Invalid rule x: Unexpected end of rule".to_string())
+        );
+        assert!(RuleSet::new(vec![r#""a""#.to_string()]).is_err());
+        assert!(RuleSet::new(vec![r#"x: "a" AND ("b""#.to_string()]).is_err());
+        assert!(RuleSet::new(vec![r#"x: ATLEAST("a")"#.to_string()]).is_err());
+    }
+
+    #[test]
+    fn rule_terms_ignoring_case() {
+        use crate::extractors::words::{SearchOptions, SearchTerms};
+
+        let rules = RuleSet::new(vec![r#"x: "password""#.to_string()]).unwrap();
+        let mut lines = vec!["Password".to_string()];
+        lines.extend(rules.terms());
+        let options = SearchOptions {
+            ignore_case: true,
+            ..SearchOptions::default()
+        };
+        let hits = SearchTerms::new(lines, &options)
+            .unwrap()
+            .search_text("Your PASSWORD is");
+
+        assert_eq!(hits.len(), 2);
+        assert_eq!(rules.evaluate(&hits), vec!["x"]);
+    }
+}
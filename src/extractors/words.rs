@@ -0,0 +1,382 @@
+//! Submodule containing functions related to searching words in a page.
+use aho_corasick::{AhoCorasick, MatchKind};
+use caseless::Caseless;
+use regex::{Regex, RegexBuilder};
+use select::{document::Document, predicate::Text};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use unicode_normalization::UnicodeNormalization;
+
+/// Maximum number of context snippets reported per term.
+const MAX_SNIPPETS: usize = 3;
+/// Number of characters on each side of a match in a snippet.
+const SNIPPET_CONTEXT: usize = 40;
+/// Elements whose text is not visible in the page.
+const HIDDEN_ELEMENTS: &[&str] = &["script", "style", "noscript", "template", "head"];
+
+/// Options for the search terms.
+/// Regex terms can also set ignore case and whole word with their own flags.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchOptions {
+    pub ignore_case: bool,
+    pub whole_word: bool,
+    pub search_markup: bool,
+}
+
+/// Matches of a search term in a page.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WordHit {
+    pub term: String,
+    pub count: usize,
+    pub snippets: Vec<String>,
+}
+
+/// A search term which is a regex.
+#[derive(Clone, Debug)]
+struct RegexTerm {
+    term: String,
+    regex: Regex,
+}
+
+impl RegexTerm {
+    /// Creates a new RegexTerm from a `/pattern/flags` line of the word list.
+    /// The flags are `i` for ignore case and `w` for whole word.
+    fn new(
+        line: &str,
+        pattern: &str,
+        flags: &str,
+        options: &SearchOptions,
+    ) -> Result<Self, String> {
+        let pattern: String = pattern.nfkc().collect();
+        let pattern = if options.whole_word || flags.contains('w') {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+        match RegexBuilder::new(&pattern)
+            .case_insensitive(options.ignore_case || flags.contains('i'))
+            .build()
+        {
+            Ok(regex) => Ok(RegexTerm {
+                term: line.to_string(),
+                regex,
+            }),
+            Err(_) => Err(format!("Invalid search term {}", line)),
+        }
+    }
+
+    /// Function to search the term in a normalized text.
+    fn search(&self, text: &Normalized, hits: &mut BTreeMap<String, WordHit>) {
+        for x in self.regex.find_iter(&text.source) {
+            if !x.as_str().is_empty() {
+                add_hit(hits, &self.term, &text.source, x.start(), x.end());
+            }
+        }
+    }
+}
+
+/// List of terms to search for in the pages.
+/// Literal terms are matched all at once with an Aho-Corasick automaton,
+/// so the cost of matching does not depend on the number of terms.
+/// Terms and texts are normalized with NFKC, and fully case folded when ignoring case.
+#[derive(Clone, Debug, Default)]
+pub struct SearchTerms {
+    literals: Vec<Vec<String>>,
+    automaton: Option<AhoCorasick>,
+    regexes: Vec<RegexTerm>,
+    options: SearchOptions,
+}
+
+impl SearchTerms {
+    /// Creates a new list of search terms from the lines of a word list.
+    /// Lines like `/pattern/flags` are regexes, everything else is a literal.
+    /// Empty lines are ignored, and literals which are the same once normalized are
+    /// only searched once, with a hit for each of their spellings.
+    pub fn new<I>(lines: I, options: &SearchOptions) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut lines: Vec<String> = lines.into_iter().collect();
+        lines.sort();
+        lines.dedup();
+        let mut literals: Vec<Vec<String>> = Vec::new();
+        let mut patterns = Vec::new();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut regexes = Vec::new();
+        for line in lines {
+            if let Some((pattern, flags)) = parse_regex_term(&line) {
+                regexes.push(RegexTerm::new(&line, pattern, flags, options)?);
+                continue;
+            }
+            let pattern = Normalized::new(&line, options.ignore_case).folded;
+            if pattern.is_empty() {
+                continue;
+            }
+            match seen.get(&pattern) {
+                Some(x) => literals[*x].push(line),
+                None => {
+                    seen.insert(pattern.clone(), patterns.len());
+                    patterns.push(pattern);
+                    literals.push(vec![line]);
+                }
+            }
+        }
+
+        let automaton = if patterns.is_empty() {
+            None
+        } else {
+            match AhoCorasick::builder()
+                .match_kind(MatchKind::Standard)
+                .build(&patterns)
+            {
+                Ok(x) => Some(x),
+                Err(_) => return Err("Could not build the search words automaton".to_string()),
+            }
+        };
+
+        Ok(SearchTerms {
+            literals,
+            automaton,
+            regexes,
+            options: *options,
+        })
+    }
+
+    /// Function to check if there are any terms to search for.
+    pub fn is_empty(&self) -> bool {
+        self.literals.is_empty() && self.regexes.is_empty()
+    }
+
+    /// Function to search the terms in a html page.
+    /// Only searches the visible text unless the markup should be searched too.
+    pub fn search_html(&self, html: &str) -> Vec<WordHit> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        if self.options.search_markup {
+            self.search_text(html)
+        } else {
+            self.search_text(&visible_text(html))
+        }
+    }
+
+    /// Function to search the terms in a text.
+    /// The hits are sorted by the term.
+    pub fn search_text(&self, text: &str) -> Vec<WordHit> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let text = Normalized::new(text, self.options.ignore_case);
+        let mut hits = BTreeMap::new();
+
+        if let Some(automaton) = &self.automaton {
+            for x in automaton.find_overlapping_iter(&text.folded) {
+                if self.options.whole_word && !is_whole_word(&text.folded, x.start(), x.end()) {
+                    continue;
+                }
+                let (start, end) = (text.offsets[x.start()], text.offsets[x.end()]);
+                for term in &self.literals[x.pattern().as_usize()] {
+                    add_hit(&mut hits, term, &text.source, start, end);
+                }
+            }
+        }
+        for x in &self.regexes {
+            x.search(&text, &mut hits);
+        }
+
+        hits.into_values().collect()
+    }
+}
+
+/// Normalized version of a text.
+/// The folded text is used for matching literals,
+/// and its offsets point back into the source text for the snippets.
+struct Normalized {
+    source: String,
+    folded: String,
+    offsets: Vec<usize>,
+}
+
+impl Normalized {
+    /// Function to normalize a text with NFKC.
+    /// Also applies the full Unicode case folding to the folded text if case should be ignored,
+    /// so ß and ẞ match ss.
+    fn new(text: &str, ignore_case: bool) -> Self {
+        let source: String = text.nfkc().collect();
+        let mut folded = String::with_capacity(source.len());
+        let mut offsets = Vec::with_capacity(source.len() + 1);
+        for (i, c) in source.char_indices() {
+            if ignore_case {
+                folded.extend(std::iter::once(c).default_case_fold());
+            } else {
+                folded.push(c);
+            }
+            offsets.resize(folded.len(), i);
+        }
+        offsets.push(source.len());
+        Normalized {
+            source,
+            folded,
+            offsets,
+        }
+    }
+}
+
+/// Function to add a match of a term to the hits.
+fn add_hit(hits: &mut BTreeMap<String, WordHit>, term: &str, text: &str, start: usize, end: usize) {
+    let hit = hits.entry(term.to_string()).or_insert_with(|| WordHit {
+        term: term.to_string(),
+        count: 0,
+        snippets: Vec::new(),
+    });
+    hit.count += 1;
+    if hit.snippets.len() < MAX_SNIPPETS {
+        hit.snippets.push(snippet(text, start, end));
+    }
+}
+
+/// Function to check if a match is not part of a bigger word.
+fn is_whole_word(text: &str, start: usize, end: usize) -> bool {
+    let is_word = |x: char| x.is_alphanumeric() || x == '_';
+    let before = text[..start].chars().next_back().is_some_and(is_word);
+    let after = text[end..].chars().next().is_some_and(is_word);
+    !before && !after
+}
+
+/// Function to split a `/pattern/flags` term.
+fn parse_regex_term(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix('/')?;
+    let (pattern, flags) = rest.rsplit_once('/')?;
+    if pattern.is_empty() || !flags.chars().all(|x| x == 'i' || x == 'w') {
+        return None;
+    }
+    Some((pattern, flags))
+}
+
+/// Function to get the visible text of a html page.
+/// Skips the text in scripts, styles and other hidden elements.
+pub fn visible_text(html: &str) -> String {
+    let document = Document::from(html);
+    let mut text = String::with_capacity(html.len() / 2);
+    for node in document.find(Text) {
+        let mut parent = node.parent();
+        let mut hidden = false;
+        while let Some(x) = parent {
+            if x.name().is_some_and(|x| HIDDEN_ELEMENTS.contains(&x)) {
+                hidden = true;
+                break;
+            }
+            parent = x.parent();
+        }
+        if !hidden {
+            if let Some(x) = node.as_text() {
+                text.push_str(x);
+                text.push(' ');
+            }
+        }
+    }
+    text
+}
+
+/// Function to get a short snippet of text around a match.
+/// Whitespace is collapsed to keep the snippet on one line.
+fn snippet(text: &str, start: usize, end: usize) -> String {
+    let start = text[..start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map_or(0, |(i, _)| i);
+    let end = text[end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map_or(text.len(), |(i, _)| end + i);
+    text[start..end]
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(lines: &[&str], options: SearchOptions) -> SearchTerms {
+        SearchTerms::new(lines.iter().map(|x| x.to_string()), &options).unwrap()
+    }
+
+    #[test]
+    fn get_words() {
+        let html = "This is a sample page which does not work";
+
+        assert!(terms(&[], SearchOptions::default())
+            .search_html(html)
+            .is_empty());
+        assert!(!terms(&["sample"], SearchOptions::default())
+            .search_html(html)
+            .is_empty());
+    }
+
+    #[test]
+    fn search_options() {
+        let html = r#"<html><head><title>Invoices</title></head>
+            <body><a href="/password">Login</a><script>var invoice = 1;</script>
+            <p>Send the INVOICE. Another invoice, invoices.</p></body></html>"#;
+
+        let hits = terms(&["invoice", "password"], SearchOptions::default()).search_html(html);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].count, 2);
+
+        let options = SearchOptions {
+            ignore_case: true,
+            whole_word: true,
+            ..SearchOptions::default()
+        };
+        let hits = terms(&["invoice"], options).search_html(html);
+        assert_eq!(hits[0].count, 2);
+        assert_eq!(
+            hits[0].snippets[0],
+            "Login Send the INVOICE. Another invoice, invoices."
+        );
+
+        let options = SearchOptions {
+            search_markup: true,
+            ..SearchOptions::default()
+        };
+        let hits = terms(&["password", "/invoices?/i"], options).search_html(html);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].term, "/invoices?/i");
+        assert_eq!(hits[0].count, 5);
+
+        assert!(SearchTerms::new(vec!["/(/".to_string()], &SearchOptions::default()).is_err());
+    }
+
+    #[test]
+    fn normalize_terms() {
+        let text = "Ｐａｓｓｗｏｒｄ: ﬁnance STRASSE café";
+        let options = SearchOptions {
+            ignore_case: true,
+            ..SearchOptions::default()
+        };
+        let hits = terms(&["password", "finance", "Strasse", "CAFÉ"], options).search_text(text);
+        assert_eq!(hits.len(), 4);
+        assert_eq!(hits[3].term, "password");
+        assert_eq!(hits[3].snippets[0], "Password: finance STRASSE café");
+
+        let hits = terms(&["pass", "password"], options).search_text(text);
+        assert_eq!(hits.len(), 2);
+
+        let hits = terms(&["straße", "STRAẞE"], options).search_text(text);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].term, "STRAẞE");
+        assert_eq!(hits[1].term, "straße");
+        assert_eq!(hits[1].count, 1);
+        assert_eq!(hits[1].snippets[0], "Password: finance STRASSE café");
+
+        let options = SearchOptions {
+            whole_word: true,
+            ..options
+        };
+        let hits = terms(&["pass", "password"], options).search_text(text);
+        assert_eq!(hits.len(), 1);
+    }
+}
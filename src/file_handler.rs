@@ -1,11 +1,22 @@
 //! Module containing functions related to File IO.
-use std::collections::HashSet;
-use std::path::PathBuf;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
 use tokio::sync::mpsc::Receiver;
 
 use crate::extractors::links;
+use crate::recrawl::PageFile;
+use crate::selenium::ScreenshotRecord;
+
+/// Name of the file with the content hashes of the crawled pages.
+pub const CONTENT_HASHES_FILE_NAME: &str = r#"content_hashes.json"#;
+/// Name of the file with the pages kept for the next crawl.
+pub const PAGES_FILE_NAME: &str = r#"pages.json"#;
+/// Name of the file with the index of the screenshots.
+pub const INDEX_FILE_NAME: &str = r#"index.json"#;
 
 /// Function to Read and return a list of hosts from a file.
 /// Used from reading whitelist and blacklist.
@@ -42,6 +53,47 @@ pub async fn read_words(file_path: PathBuf) -> Result<HashSet<String>, Box<dyn s
     Ok(words)
 }
 
+/// Function to read and return the lines of a file in order.
+/// Used for reading Rules.
+pub async fn read_lines(file_path: PathBuf) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let file = File::open(file_path).await?;
+    let reader = BufReader::new(file);
+    let mut list = reader.lines();
+    let mut lines = Vec::new();
+    while let Ok(Some(x)) = list.next_line().await {
+        lines.push(x);
+    }
+
+    Ok(lines)
+}
+
+/// Function to read DNS overrides from a file in hosts file format.
+/// Each line contains an address followed by the names it should resolve to.
+pub async fn read_dns_overrides(
+    file_path: PathBuf,
+) -> Result<HashMap<String, Vec<IpAddr>>, Box<dyn std::error::Error>> {
+    let file = File::open(file_path).await?;
+    let reader = BufReader::new(file);
+    let mut list = reader.lines();
+    let mut overrides: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    while let Ok(Some(x)) = list.next_line().await {
+        let line = match x.split('#').next() {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut fields = line.split_whitespace();
+        let ip = match fields.next().map(|x| x.parse::<IpAddr>()) {
+            Some(Ok(x)) => x,
+            _ => continue,
+        };
+        for name in fields {
+            overrides.entry(name.to_string()).or_default().push(ip);
+        }
+    }
+
+    Ok(overrides)
+}
+
 /// Function to write links to files.
 /// Seperate files for crawled, not crawled, emails and phone no.
 pub async fn write_links(
@@ -81,28 +133,72 @@ pub async fn write_links(
     Ok(())
 }
 
+/// Function to read the content hashes written by the last crawl into a folder.
+/// Returns no hashes if there was no last crawl.
+pub async fn read_content_hashes(
+    folder_path: PathBuf,
+) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let json = match tokio::fs::read(folder_path.join(CONTENT_HASHES_FILE_NAME)).await {
+        Ok(x) => x,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// Function to read the pages written by the last crawl into a folder.
+/// Returns no pages if there was no last crawl.
+pub async fn read_pages(folder_path: PathBuf) -> Result<PageFile, Box<dyn std::error::Error>> {
+    let json = match tokio::fs::read(folder_path.join(PAGES_FILE_NAME)).await {
+        Ok(x) => x,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(PageFile::default()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// Function to read the index of the screenshots in a folder.
+pub async fn read_screenshot_index(
+    folder_path: PathBuf,
+) -> Result<Vec<ScreenshotRecord>, Box<dyn std::error::Error>> {
+    let json = tokio::fs::read(folder_path.join(INDEX_FILE_NAME)).await?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// Function to write a report of the crawl as json to a file in a folder.
+pub async fn write_json_file<T: Serialize + ?Sized>(
+    folder_path: PathBuf,
+    file_name: &str,
+    value: &T,
+) -> Result<(), std::io::Error> {
+    let json = serde_json::to_vec_pretty(value)?;
+    let mut writer = BufWriter::new(File::create(folder_path.join(file_name)).await?);
+    writer.write_all(&json).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
 /// Intialize BufWriter
 async fn init_writer(
     file_name: &str,
-    folder_path: &PathBuf,
+    folder_path: &Path,
 ) -> Result<BufWriter<File>, std::io::Error> {
-    let mut file_path = folder_path.clone();
-    file_path.push(file_name);
+    let file_path = folder_path.join(file_name);
     let mut writer = BufWriter::new(File::create(file_path).await?);
-    writer.write(b"[\n").await?;
+    writer.write_all(b"[\n").await?;
     Ok(writer)
 }
 
 /// Write Json Link to a file.
 async fn write_json(writer: &mut BufWriter<File>, json: &str) -> Result<(), std::io::Error> {
-    writer.write(json.as_bytes()).await?;
-    writer.write(b",\n").await?;
+    writer.write_all(json.as_bytes()).await?;
+    writer.write_all(b",\n").await?;
     Ok(())
 }
 
 /// Clean up after writers.
 async fn clean_writer(writer: &mut BufWriter<File>) -> Result<(), std::io::Error> {
-    writer.write(b"{}\n]").await?;
+    writer.write_all(b"{}\n]").await?;
     writer.flush().await?;
     Ok(())
 }
@@ -0,0 +1,573 @@
+/*!
+Module containing the frontier of the crawler.
+*/
+use crate::extractors::words::SearchTerms;
+use crate::store::{db_error, Bloom, Store, UrlSet};
+use reqwest::Url;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Order in which the urls are crawled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    /// Shallowest urls first.
+    Breadth,
+    /// Deepest urls first, and the newest of them.
+    Depth,
+    /// Urls with the highest score first.
+    Best,
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bfs" | "breadth-first" => Ok(Order::Breadth),
+            "dfs" | "depth-first" => Ok(Order::Depth),
+            "best" | "best-first" => Ok(Order::Best),
+            _ => Err(format!("Unknown order {}", s)),
+        }
+    }
+}
+
+/// Url found by the crawler, as seen by the score functions.
+pub struct Candidate<'a> {
+    pub url: &'a Url,
+    pub depth: usize,
+    pub sitemap_priority: Option<f64>,
+}
+
+/// Function scoring the urls for best-first order, where higher scores are crawled first.
+pub type ScoreFn = Arc<dyn Fn(&Candidate) -> f64 + Send + Sync>;
+
+/// Built in scores for best-first order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Score {
+    /// Number of search words in the url.
+    Words,
+    /// Priority of the url in the sitemap, 0.5 if it is not in one.
+    Sitemap,
+    /// Higher for urls with fewer path segments, between 0 and 1.
+    Shallow,
+    /// Higher for urls fewer links away from the seed url, between 0 and 1.
+    Near,
+}
+
+impl FromStr for Score {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "words" => Ok(Score::Words),
+            "sitemap" => Ok(Score::Sitemap),
+            "shallow" => Ok(Score::Shallow),
+            "near" => Ok(Score::Near),
+            _ => Err(format!("Unknown score {}", s)),
+        }
+    }
+}
+
+/// Function to build a score function adding up the given scores.
+/// All of them are used if none are given.
+pub fn score_fn(scores: &[Score], word_list: SearchTerms) -> ScoreFn {
+    let scores = if scores.is_empty() {
+        vec![Score::Words, Score::Sitemap, Score::Shallow, Score::Near]
+    } else {
+        scores.to_vec()
+    };
+    Arc::new(move |x: &Candidate| {
+        scores
+            .iter()
+            .map(|score| match score {
+                Score::Words => {
+                    let url = percent_encoding::percent_decode_str(x.url.as_str());
+                    word_list.search_text(&url.decode_utf8_lossy()).len() as f64
+                }
+                Score::Sitemap => x.sitemap_priority.unwrap_or(0.5),
+                Score::Shallow => {
+                    let segments = x
+                        .url
+                        .path_segments()
+                        .map_or(0, |x| x.filter(|x| !x.is_empty()).count());
+                    1.0 / (1.0 + segments as f64)
+                }
+                Score::Near => 1.0 / (1.0 + x.depth as f64),
+            })
+            .sum()
+    })
+}
+
+/// Strategy deciding which url of the frontier is crawled next.
+#[derive(Clone)]
+pub struct Strategy {
+    pub order: Order,
+    pub round_robin: bool,
+    pub score: ScoreFn,
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        Strategy {
+            order: Order::Breadth,
+            round_robin: false,
+            score: Arc::new(|_: &Candidate| 0.0),
+        }
+    }
+}
+
+impl fmt::Debug for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Strategy")
+            .field("order", &self.order)
+            .field("round_robin", &self.round_robin)
+            .finish()
+    }
+}
+
+/// Url waiting in the frontier.
+/// Entries with a higher rank come first, and the older one if the ranks are the same.
+#[derive(Debug)]
+struct Entry {
+    rank: (f64, f64),
+    pushed: u64,
+    depth: usize,
+    url: Url,
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank
+            .0
+            .total_cmp(&other.rank.0)
+            .then(self.rank.1.total_cmp(&other.rank.1))
+            .then(other.pushed.cmp(&self.pushed))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+/// Queues of the urls waiting, by host, with the depth of every url.
+/// On disk the entries of a host are keys starting with the host, ordered by their rank,
+/// and only the number of entries of every host is kept in memory.
+#[derive(Debug)]
+enum Queue {
+    Memory {
+        hosts: HashMap<String, BinaryHeap<Entry>>,
+        depths: HashMap<Url, usize>,
+    },
+    Disk {
+        entries: sled::Tree,
+        depths: sled::Tree,
+        bloom: Option<Bloom>,
+        hosts: HashMap<String, usize>,
+        waiting: usize,
+    },
+}
+
+impl Queue {
+    /// Function to get the depth a url is waiting at.
+    fn depth(&self, url: &Url) -> Result<Option<usize>, String> {
+        match self {
+            Queue::Memory { depths, .. } => Ok(depths.get(url).copied()),
+            Queue::Disk { depths, bloom, .. } => {
+                if bloom.as_ref().is_some_and(|x| !x.contains(url.as_str())) {
+                    return Ok(None);
+                }
+                Ok(depths
+                    .get(url.as_str())
+                    .map_err(db_error)?
+                    .map(|x| decode_depth(&x)))
+            }
+        }
+    }
+
+    /// Function to add an entry to the queue of a host.
+    /// Returns true if the host had no entries.
+    fn push(&mut self, host: String, entry: Entry) -> Result<bool, String> {
+        match self {
+            Queue::Memory { hosts, depths } => {
+                depths.insert(entry.url.clone(), entry.depth);
+                let new_host = !hosts.contains_key(&host);
+                hosts.entry(host).or_default().push(entry);
+                Ok(new_host)
+            }
+            Queue::Disk {
+                entries,
+                depths,
+                bloom,
+                hosts,
+                waiting,
+            } => {
+                if let Some(x) = bloom {
+                    x.insert(entry.url.as_str());
+                }
+                let depth = (entry.depth as u64).to_be_bytes();
+                if depths
+                    .insert(entry.url.as_str(), &depth)
+                    .map_err(db_error)?
+                    .is_none()
+                {
+                    *waiting += 1;
+                }
+                entries
+                    .insert(entry_key(&host, &entry), &depth)
+                    .map_err(db_error)?;
+                let count = hosts.entry(host).or_insert(0);
+                *count += 1;
+                Ok(*count == 1)
+            }
+        }
+    }
+
+    /// Function to take the first url of a host which is still waiting at its depth.
+    /// Also returns if the host has entries left.
+    fn pop(&mut self, host: &str) -> Result<(Option<(Url, usize)>, bool), String> {
+        match self {
+            Queue::Memory { hosts, depths } => {
+                let queue = match hosts.get_mut(host) {
+                    Some(x) => x,
+                    None => return Ok((None, false)),
+                };
+                let mut next = None;
+                while let Some(x) = queue.pop() {
+                    if depths.get(&x.url) == Some(&x.depth) {
+                        depths.remove(&x.url);
+                        next = Some((x.url, x.depth));
+                        break;
+                    }
+                }
+                let left = !queue.is_empty();
+                if !left {
+                    hosts.remove(host);
+                }
+                Ok((next, left))
+            }
+            Queue::Disk {
+                entries,
+                depths,
+                hosts,
+                waiting,
+                ..
+            } => {
+                let mut prefix = host.as_bytes().to_vec();
+                prefix.push(0);
+                let mut next = None;
+                while let Some((key, value)) = entries
+                    .scan_prefix(&prefix)
+                    .next()
+                    .transpose()
+                    .map_err(db_error)?
+                {
+                    entries.remove(&key).map_err(db_error)?;
+                    if let Some(x) = hosts.get_mut(host) {
+                        *x -= 1;
+                    }
+                    let url = match Url::parse(&String::from_utf8_lossy(&key[prefix.len() + 24..]))
+                    {
+                        Ok(x) => x,
+                        Err(_) => continue,
+                    };
+                    let depth = decode_depth(&value);
+                    let waiting_depth = depths.get(url.as_str()).map_err(db_error)?;
+                    if waiting_depth.is_some_and(|x| decode_depth(&x) == depth) {
+                        depths.remove(url.as_str()).map_err(db_error)?;
+                        *waiting -= 1;
+                        next = Some((url, depth));
+                        break;
+                    }
+                }
+                let left = hosts.get(host).is_some_and(|x| *x > 0);
+                if !left {
+                    hosts.remove(host);
+                }
+                Ok((next, left))
+            }
+        }
+    }
+
+    /// Function to get the number of urls waiting.
+    fn len(&self) -> usize {
+        match self {
+            Queue::Memory { depths, .. } => depths.len(),
+            Queue::Disk { waiting, .. } => *waiting,
+        }
+    }
+}
+
+/// Function to get the key of an entry on disk.
+/// The key is the host, the ranks and the push counter, so keys sort in the order of the
+/// entries, followed by the url.
+fn entry_key(host: &str, entry: &Entry) -> Vec<u8> {
+    /// Function to encode a float so the bytes sort from the highest to the lowest float.
+    fn descending(x: f64) -> [u8; 8] {
+        let bits = x.to_bits();
+        let ascending = if bits >> 63 == 1 {
+            !bits
+        } else {
+            bits | 1 << 63
+        };
+        (!ascending).to_be_bytes()
+    }
+
+    let mut key = host.as_bytes().to_vec();
+    key.push(0);
+    key.extend_from_slice(&descending(entry.rank.0));
+    key.extend_from_slice(&descending(entry.rank.1));
+    key.extend_from_slice(&entry.pushed.to_be_bytes());
+    key.extend_from_slice(entry.url.as_str().as_bytes());
+    key
+}
+
+/// Function to decode a depth stored on disk.
+fn decode_depth(bytes: &[u8]) -> usize {
+    let mut depth = [0; 8];
+    depth.copy_from_slice(&bytes[..8.min(bytes.len())]);
+    u64::from_be_bytes(depth) as usize
+}
+
+/// Urls waiting to be crawled, ordered by the strategy.
+/// Without round-robin all urls wait in the queue of the empty host.
+#[derive(Debug)]
+pub struct Frontier {
+    strategy: Strategy,
+    queue: Queue,
+    turns: VecDeque<String>,
+    visited: UrlSet,
+    pushed: u64,
+}
+
+impl Frontier {
+    /// Creates a new empty Frontier keeping its urls in the store.
+    pub fn new(strategy: Strategy, store: &Store) -> Result<Self, String> {
+        let queue = match (store.tree("frontier")?, store.tree("depths")?) {
+            (Some(entries), Some(depths)) => Queue::Disk {
+                entries,
+                depths,
+                bloom: store.bloom(),
+                hosts: HashMap::new(),
+                waiting: 0,
+            },
+            _ => Queue::Memory {
+                hosts: HashMap::new(),
+                depths: HashMap::new(),
+            },
+        };
+        Ok(Frontier {
+            strategy,
+            queue,
+            turns: VecDeque::new(),
+            visited: store.url_set("visited")?,
+            pushed: 0,
+        })
+    }
+
+    /// Function to add a url found at a depth, with its priority in the sitemap if it has one.
+    /// Returns false if the url was already handed out or is already waiting at this depth or less.
+    pub fn push(
+        &mut self,
+        url: Url,
+        depth: usize,
+        sitemap_priority: Option<f64>,
+    ) -> Result<bool, String> {
+        if self.visited.contains(&url)? || self.queue.depth(&url)?.is_some_and(|x| x <= depth) {
+            return Ok(false);
+        }
+        let rank = match self.strategy.order {
+            Order::Breadth => (-(depth as f64), 0.0),
+            Order::Depth => (depth as f64, self.pushed as f64),
+            Order::Best => {
+                let candidate = Candidate {
+                    url: &url,
+                    depth,
+                    sitemap_priority,
+                };
+                ((self.strategy.score)(&candidate), -(depth as f64))
+            }
+        };
+        let host = if self.strategy.round_robin {
+            url.host_str().unwrap_or_default().to_string()
+        } else {
+            String::new()
+        };
+        let entry = Entry {
+            rank,
+            pushed: self.pushed,
+            depth,
+            url,
+        };
+        if self.queue.push(host.clone(), entry)? {
+            self.turns.push_back(host);
+        }
+        self.pushed += 1;
+        Ok(true)
+    }
+
+    /// Function to get the next url to crawl with its depth.
+    /// Entries left behind by urls which moved to another depth are skipped.
+    pub fn pop(&mut self) -> Result<Option<(Url, usize)>, String> {
+        while let Some(host) = self.turns.pop_front() {
+            let (next, left) = self.queue.pop(&host)?;
+            if left {
+                self.turns.push_back(host);
+            }
+            if let Some((url, depth)) = next {
+                self.visited.insert(url.clone())?;
+                return Ok(Some((url, depth)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Function to check if a url was already handed out.
+    pub fn is_visited(&self, url: &Url) -> Result<bool, String> {
+        self.visited.contains(url)
+    }
+
+    /// Function to get the number of urls waiting.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Function to check if no urls are waiting.
+    pub fn is_empty(&self) -> bool {
+        self.queue.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extractors::words::SearchOptions;
+
+    fn url(x: &str) -> Url {
+        Url::parse(&format!("https://example.com/{}", x)).unwrap()
+    }
+
+    fn pop_all(frontier: &mut Frontier) -> Vec<String> {
+        std::iter::from_fn(|| frontier.pop().unwrap())
+            .map(|(x, _)| x.to_string())
+            .collect()
+    }
+
+    /// Function to run a test with the urls in memory and on disk.
+    fn with_stores(name: &str, test: impl Fn(&Store)) {
+        test(&Store::memory());
+        let path = std::env::temp_dir().join(format!("crate-{}-{}", name, std::process::id()));
+        let store = Store::open(&path, Some(100)).unwrap();
+        test(&store);
+        drop(store);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn frontier_order() {
+        with_stores("frontier-order", |store| {
+            let mut frontier = Frontier::new(Strategy::default(), store).unwrap();
+
+            assert!(frontier.push(url("b"), 2, None).unwrap());
+            assert!(frontier.push(url("a"), 1, None).unwrap());
+            assert!(frontier.push(url("c"), 2, None).unwrap());
+            assert!(!frontier.push(url("a"), 3, None).unwrap());
+            assert!(frontier.push(url("c"), 1, None).unwrap());
+            assert_eq!(frontier.len(), 3);
+
+            assert_eq!(frontier.pop().unwrap(), Some((url("a"), 1)));
+            assert_eq!(frontier.pop().unwrap(), Some((url("c"), 1)));
+            assert!(!frontier.push(url("a"), 0, None).unwrap());
+            assert!(frontier.is_visited(&url("c")).unwrap());
+            assert_eq!(frontier.pop().unwrap(), Some((url("b"), 2)));
+            assert_eq!(frontier.pop().unwrap(), None);
+            assert!(frontier.is_empty());
+        });
+    }
+
+    #[test]
+    fn strategies() {
+        with_stores("strategies", |store| {
+            let mut frontier = Frontier::new(
+                Strategy {
+                    order: Order::Depth,
+                    ..Strategy::default()
+                },
+                store,
+            )
+            .unwrap();
+            frontier.push(url("a"), 1, None).unwrap();
+            frontier.push(url("b"), 1, None).unwrap();
+            frontier.push(url("a/x"), 2, None).unwrap();
+            assert_eq!(
+                pop_all(&mut frontier),
+                vec![url("a/x").as_str(), url("b").as_str(), url("a").as_str()]
+            );
+
+            let word_list =
+                SearchTerms::new(vec!["invoice".to_string()], &SearchOptions::default()).unwrap();
+            let mut frontier = Frontier::new(
+                Strategy {
+                    order: Order::Best,
+                    round_robin: false,
+                    score: score_fn(&[], word_list),
+                },
+                store,
+            )
+            .unwrap();
+            frontier.push(url("a/b/c"), 1, None).unwrap();
+            frontier.push(url("about"), 1, Some(0.1)).unwrap();
+            frontier.push(url("news"), 1, Some(0.9)).unwrap();
+            frontier.push(url("x/invoice%20list"), 2, None).unwrap();
+            assert_eq!(
+                pop_all(&mut frontier),
+                vec![
+                    url("x/invoice%20list").as_str(),
+                    url("news").as_str(),
+                    url("a/b/c").as_str(),
+                    url("about").as_str()
+                ]
+            );
+
+            let mut frontier = Frontier::new(
+                Strategy {
+                    round_robin: true,
+                    ..Strategy::default()
+                },
+                store,
+            )
+            .unwrap();
+            for x in &["https://a.com/1", "https://a.com/2", "https://a.com/3"] {
+                frontier.push(Url::parse(x).unwrap(), 1, None).unwrap();
+            }
+            frontier
+                .push(Url::parse("https://b.com/1").unwrap(), 1, None)
+                .unwrap();
+            frontier
+                .push(Url::parse("https://b.com/2").unwrap(), 1, None)
+                .unwrap();
+            assert_eq!(
+                pop_all(&mut frontier),
+                vec![
+                    "https://a.com/1",
+                    "https://b.com/1",
+                    "https://a.com/2",
+                    "https://b.com/2",
+                    "https://a.com/3"
+                ]
+            );
+        });
+    }
+}
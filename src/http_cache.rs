@@ -0,0 +1,260 @@
+/*!
+Module containing the cache of http responses on disk.
+*/
+use crate::extractors::content::fnv1a;
+use hyper::http;
+use reqwest::header::{self, HeaderMap};
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How the cache is used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CacheMode {
+    /// Answer fresh responses from the cache and keep the new ones.
+    Normal,
+    /// Answer every response from the cache, never touching the network.
+    Offline,
+}
+
+/// Response kept in the cache, without its body.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    url: String,
+    status: u16,
+    #[serde(with = "http_serde::header_map")]
+    headers: HeaderMap,
+    /// Seconds since the unix epoch when the response stops being fresh.
+    fresh_until: u64,
+}
+
+/// Cache of http responses in a folder.
+#[derive(Clone, Debug)]
+pub struct HttpCache {
+    folder: PathBuf,
+    mode: CacheMode,
+    ttl: Duration,
+    max_size: usize,
+}
+
+impl HttpCache {
+    /// Creates a new HttpCache in the folder.
+    /// Responses without freshness information are fresh for the ttl,
+    /// and bodies bigger than the maximum size are not kept.
+    pub fn new(folder: PathBuf, mode: CacheMode, ttl: Duration, max_size: usize) -> Self {
+        HttpCache {
+            folder,
+            mode,
+            ttl,
+            max_size,
+        }
+    }
+
+    /// Function to get the paths of the headers and the body of an url.
+    fn paths(&self, url: &str) -> (PathBuf, PathBuf) {
+        let name = format!("{:016x}", fnv1a(url.as_bytes()));
+        (
+            self.folder.join(format!("{}.json", name)),
+            self.folder.join(format!("{}.body", name)),
+        )
+    }
+
+    /// Function to get the response of an url from the cache.
+    /// Returns None if it is not kept or not fresh, unless in offline replay mode
+    /// where urls which are not kept get a 504 response.
+    pub async fn get(&self, url: &str) -> Option<Response> {
+        let cached = self.read(url).await;
+        if self.mode == CacheMode::Offline {
+            return Some(cached.map_or_else(
+                || {
+                    build_response(
+                        StatusCode::GATEWAY_TIMEOUT.as_u16(),
+                        HeaderMap::new(),
+                        Vec::new(),
+                    )
+                },
+                |(x, body)| build_response(x.status, x.headers, body),
+            ));
+        }
+        let (cached, body) = cached?;
+        if cached.fresh_until <= now() {
+            return None;
+        }
+        Some(build_response(cached.status, cached.headers, body))
+    }
+
+    /// Function to read the response of an url kept in the cache.
+    async fn read(&self, url: &str) -> Option<(CachedResponse, Vec<u8>)> {
+        let (headers_path, body_path) = self.paths(url);
+        let json = tokio::fs::read(headers_path).await.ok()?;
+        let cached: CachedResponse = serde_json::from_slice(&json).ok()?;
+        if cached.url != url {
+            return None;
+        }
+        let body = tokio::fs::read(body_path).await.ok()?;
+        Some((cached, body))
+    }
+
+    /// Function to keep a response from the network in the cache, if it can be kept.
+    /// The body is read to keep it, so a new response with the same body is returned.
+    /// Returns the error if the body could not be read to its end.
+    pub async fn put(&self, url: &str, mut resp: Response) -> Result<Response, reqwest::Error> {
+        let fresh_for = match freshness(resp.headers(), self.ttl) {
+            Some(x) if resp.status() == StatusCode::OK => x,
+            _ => return Ok(resp),
+        };
+        if resp
+            .content_length()
+            .is_some_and(|x| x > self.max_size as u64)
+        {
+            return Ok(resp);
+        }
+        let status = resp.status().as_u16();
+        let headers = resp.headers().clone();
+        let mut body = Vec::new();
+        let mut complete = true;
+        while let Some(x) = resp.chunk().await? {
+            body.extend_from_slice(&x);
+            if body.len() > self.max_size {
+                complete = false;
+                break;
+            }
+        }
+        if complete {
+            let cached = CachedResponse {
+                url: url.to_string(),
+                status,
+                headers: headers.clone(),
+                fresh_until: now() + fresh_for.as_secs(),
+            };
+            let _ = self.write(&cached, &body).await;
+        }
+        Ok(build_response(status, headers, body))
+    }
+
+    /// Function to write a response to the cache.
+    async fn write(&self, cached: &CachedResponse, body: &[u8]) -> Result<(), std::io::Error> {
+        let (headers_path, body_path) = self.paths(&cached.url);
+        tokio::fs::create_dir_all(&self.folder).await?;
+        tokio::fs::write(body_path, body).await?;
+        tokio::fs::write(headers_path, serde_json::to_vec(cached)?).await
+    }
+}
+
+/// Function to get how long a response stays fresh from its headers.
+/// Returns None if it should not be kept.
+fn freshness(headers: &HeaderMap, ttl: Duration) -> Option<Duration> {
+    let cache_control = headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|x| x.to_str().ok())
+        .flat_map(|x| x.split(','))
+        .map(|x| x.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    if cache_control.iter().any(|x| x == "no-store") {
+        return None;
+    }
+    if cache_control.iter().any(|x| x == "no-cache") {
+        return Some(Duration::from_secs(0));
+    }
+    let max_age = cache_control
+        .iter()
+        .filter_map(|x| x.strip_prefix("max-age="))
+        .find_map(|x| x.trim_matches('"').parse().ok());
+    if let Some(x) = max_age {
+        return Some(Duration::from_secs(x));
+    }
+    let expires = headers
+        .get(header::EXPIRES)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| httpdate::parse_http_date(x).ok());
+    match expires {
+        Some(x) => Some(x.duration_since(SystemTime::now()).unwrap_or_default()),
+        None => Some(ttl),
+    }
+}
+
+/// Function to get the seconds since the unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
+
+/// Function to build a response from its parts.
+fn build_response(status: u16, headers: HeaderMap, body: Vec<u8>) -> Response {
+    let mut builder = http::Response::builder().status(status);
+    if let Some(x) = builder.headers_mut() {
+        *x = headers;
+    }
+    let resp = builder
+        .body(body)
+        .unwrap_or_else(|_| http::Response::new(Vec::new()));
+    Response::from(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn cache_freshness() {
+        let ttl = Duration::from_secs(60);
+        let headers = |name, value| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_static(value));
+            headers
+        };
+        assert_eq!(freshness(&HeaderMap::new(), ttl), Some(ttl));
+        assert_eq!(
+            freshness(&headers(header::CACHE_CONTROL, "public, max-age=300"), ttl),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            freshness(&headers(header::CACHE_CONTROL, "no-cache"), ttl),
+            Some(Duration::from_secs(0))
+        );
+        assert_eq!(
+            freshness(&headers(header::CACHE_CONTROL, "No-Store"), ttl),
+            None
+        );
+        assert_eq!(
+            freshness(
+                &headers(header::EXPIRES, "Thu, 01 Jan 1970 00:00:00 GMT"),
+                ttl
+            ),
+            Some(Duration::from_secs(0))
+        );
+    }
+
+    #[tokio::test]
+    async fn broken_body() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\nshort")
+                .await;
+        });
+        let folder = std::env::temp_dir().join(format!("crate-broken-{}", std::process::id()));
+        let cache = HttpCache::new(
+            folder.clone(),
+            CacheMode::Normal,
+            Duration::from_secs(60),
+            1 << 20,
+        );
+        let resp = reqwest::get(&url).await.unwrap();
+
+        assert!(cache.put(&url, resp).await.is_err());
+        assert!(cache.get(&url).await.is_none());
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+}
@@ -1,8 +1,8 @@
 /*!
 # Introduction
 - The project basically crawls the webpage and collects as much information as possible,
-like external links, mails, etc. Like a web crawler used by search engines but specific for
-a domain and url.
+  like external links, mails, etc. Like a web crawler used by search engines but specific for
+  a domain and url.
 - It is a project for WOC.
 # CLI Usage
 ```
@@ -16,26 +16,205 @@ ARGS:
     <url>    Seed url for crawler
 
 FLAGS:
-    -h, --help        Prints help information
-        --selenium    Flag for taking screenshots using Selenium. Takes screenshot if a word from
-                      wordlist is found in the page
-        --verbose     Output the link to standard output
-    -V, --version     Prints version information
+        --archive              Save the body of every fetched page into a browsable mirror in the
+                               output folder, with the links between saved pages rewritten to local
+                               paths and a manifest of the urls
+        --block-private        Refuse to connect to private, loopback and link-local addresses
+        --full-page            Take screenshots of the whole page instead of only the visible part
+        --headless             Run the browser without a window
+    -h, --help                 Prints help information
+        --ignore-case          Ignore case when searching for words
+        --no-dns               Disable the DNS lookups for hosts and mail domains
+        --no-trap-detection    Disable the detection of crawler traps
+        --offline              Answer every request from the http cache, however old the response,
+                               without touching the network. Urls not in the cache get a 504
+                               response. Also disables the DNS lookups, and cannot be used with
+                               --render or --selenium, which would load the pages from the network
+        --recrawl              Re-crawl the pages of the last crawl into the output folder with
+                               conditional requests. Pages not modified since then keep their links
+                               and word hits from the last crawl, and pages are reported as new,
+                               changed, unchanged or gone. The pages are only kept for the next
+                               crawl when this is given
+        --render               Render html pages in the browser and extract the links and words from
+                               the rendered DOM. Cannot be used with --block-private or --block-
+                               range, which the browser does not follow
+        --reverse-dns          Also perform reverse DNS (PTR) lookups for the addresses of every
+                               host
+        --round-robin-hosts    Let the hosts take turns when picking the next page to crawl
+        --save-dom             Save the rendered DOM of the page next to every screenshot
+        --search-markup        Search for words in the html markup instead of only the visible text
+        --selenium             Flag for taking screenshots using Selenium. Takes screenshot if the
+                               page is flagged by the words or rules, or by the screenshot triggers
+        --verbose              Output the link to standard output
+    -V, --version              Prints version information
+        --whole-word           Only match whole words when searching for words
 
 OPTIONS:
-    -b, --blacklist <blacklist>            Path of file containing list of domains not to be crawled
-    -d, --depth <depth>                    Gives numeric depth for crawl
-    -o, --output-folder <output-folder>    Path to the output folder
-    -s, --search-words <search-words>      Path to file containing words to search for in the page
-        --task-limit <task-limit>          Limits the number of parallel tasks [default: 1000]
-    -t, --timeout <timeout>                Timout for http requests [default: 10]
-    -w, --whitelist <whitelist>            Path of file containing list of domains to be crawled
+        --archive-mime <archive-mime>...
+            Only save bodies of this mime type in the mirror, like text/html, or image for every
+            image type. Can be given multiple times
+
+    -b, --blacklist <blacklist>
+            Path of file containing list of domains not to be crawled
+
+        --block-range <block-range>...
+            Refuse to connect to addresses in a CIDR range. Can be given multiple times
+
+        --bloom-size <bloom-size>
+            Expected number of urls for a Bloom filter in front of the frontier database, which
+            answers most checks for new urls without reading the disk
+
+        --browser <browser>
+            Browser used for screenshots, chrome or firefox [default: chrome]
+
+        --browser-arg <browser-arg>...
+            Extra argument passed to the browser, like --browser-arg=--disable-gpu. Can be given
+            multiple times
+
+        --cache-ttl <cache-ttl>
+            Seconds for which responses without Cache-Control or Expires headers are fresh [default:
+            3600]
+
+        --compare-screenshots <compare-screenshots>
+            Path to the screenshots folder of an earlier crawl to compare the new screenshots with.
+            Diff images of the pages which changed are written to screenshots/diff, and the pages
+            are reported in screenshots/visual_diff.json
+
+    -d, --depth <depth>                                          Gives numeric depth for crawl
+        --diff-threshold <diff-threshold>
+            Share of the area of a screenshot which has to change for the page to be reported by the
+            visual diff, between 0 and 1 [default: 0.01]
+
+        --dns-hosts <dns-hosts>
+            Path of file containing DNS overrides in hosts file format
+
+        --frontier-db <frontier-db>
+            Path of an embedded database keeping the frontier and the seen urls on disk instead of
+            in memory, for crawls bigger than the memory. The database is cleared when the crawl
+            starts
+
+        --http-cache <http-cache>
+            Path of a folder keeping the http responses, which are answered from it while fresh
+
+        --max-body-size <max-body-size>
+            Maximum size of a page body in bytes. Bigger bodies are truncated [default: 10485760]
+
+        --max-bytes <max-bytes>
+            Stop the crawl after downloading this many bytes of page bodies
+
+        --max-duration <max-duration>
+            Stop the crawl after this many seconds. The pages already started are finished
+
+        --max-pages <max-pages>
+            Stop the crawl after this many pages
+
+        --max-pages-per-host <max-pages-per-host>
+            Crawl at most this many pages of every host
+
+        --max-path-depth <max-path-depth>
+            Cut urls with more path segments than this [default: 20]
+
+        --max-query-variants <max-query-variants>
+            Cut urls of a path after this many distinct query strings of it [default: 100]
+
+        --max-segment-repeats <max-segment-repeats>
+            Cut urls with a path segment appearing more times than this [default: 3]
+
+        --max-similar-pages <max-similar-pages>
+            Stop expanding an url pattern after this many pages of it with the same content,
+            ignoring digits and whitespace [default: 10]
+
+        --max-url-length <max-url-length>
+            Cut urls longer than this many characters [default: 2048]
+
+        --nameserver <nameserver>...
+            Nameserver to use instead of the system configuration. Format is
+            [udp|tcp|tls|https://]ip[:port][#tls-name]. Can be given multiple times
+
+        --near-duplicate-threshold <near-duplicate-threshold>
+            Minimum similarity of the text of two pages, from 0 to 1, to report them as near-
+            duplicates [default: 0.9]
+
+        --order <order>
+            Order of crawling the pages: bfs for breadth-first, dfs for depth-first, or best for
+            best-first by the scores of the urls [default: bfs]
+
+    -o, --output-folder <output-folder>                          Path to the output folder
+        --page-load-timeout <page-load-timeout>
+            Timeout in seconds for loading a page in the browser [default: 30]
+
+        --render-min-links <render-min-links>
+            Only render pages with fewer links than this in their static html
+
+        --render-rule <render-rule>...
+            Only render pages matching the rule with this name. Can be given multiple times
+
+        --render-wait <render-wait>
+            Maximum time in seconds to wait for the network to become idle when rendering a page
+            [default: 10]
+
+        --rules <rules>
+            Path to file containing named rules over the search words, like leak: ("invoice" AND
+            "password") NOT "example". Pages are flagged by the rules instead of any word
+
+        --score <score>...
+            Score of the urls for best-first order: words for the search words in the url, sitemap
+            for the priority in the sitemap, shallow for urls with fewer path segments, and near for
+            urls fewer links away from the seed url. Scores are added up, and all of them are used
+            if none are given. Can be given multiple times
+
+        --screenshot-on <screenshot-on>...
+            Take screenshots of the pages for which a trigger fires, instead of the flagged pages.
+            Triggers are flagged, depth:N for pages at most N links from the seed url, url:REGEX,
+            status, status:404 or status:5xx for error status codes, and changed for pages whose
+            content changed since the last crawl into the output folder. Can be given multiple times
+
+        --screenshot-timeout <screenshot-timeout>
+            Timeout in seconds for taking the screenshot of a page. The session is replaced by a new
+            one if it takes longer [default: 60]
+
+    -s, --search-words <search-words>
+            Path to file containing words to search for in the page. Lines like /pattern/flags are
+            regexes, with flags i for ignore case and w for whole word
+
+        --selenium-sessions <selenium-sessions>
+            Number of browser sessions used in parallel, for screenshots and for rendering each
+            [default: 1]
+
+        --task-limit <task-limit>
+            Maximum number of pages crawled at the same time. Also the size of the channels between
+            the tasks [default: 1000]
+
+    -t, --timeout <timeout>
+            Timout for http requests [default: 10]
+
+        --webdriver-url <webdriver-url>
+            Url of the WebDriver server used for screenshots [default: http://localhost:4444/wd/hub]
+
+    -w, --whitelist <whitelist>
+            Path of file containing list of domains to be crawled
+
+        --window-size <window-size>
+            Size of the browser window like 1920x1080. The window is made fullscreen if not given
 ```
 */
+mod budget;
 mod cli;
 mod crawler;
+mod dedup;
+mod dns;
 mod extractors;
 mod file_handler;
+mod frontier;
+mod http_cache;
+mod mirror;
+mod policy;
+mod recrawl;
+mod selenium;
+mod store;
+mod traps;
+mod triggers;
+mod visual_diff;
 
 #[tokio::main]
 async fn main() {
@@ -0,0 +1,412 @@
+/*!
+Module containing the archive of the crawled pages.
+*/
+use crate::extractors::content::fnv1a;
+use mime::Mime;
+use regex::{Captures, Regex};
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Name of the file mapping the urls to their files.
+const MANIFEST_FILE_NAME: &str = r#"manifest.json"#;
+
+/// File of a saved url, in the manifest.
+#[derive(Clone, Debug, Serialize)]
+pub struct MirrorEntry {
+    pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(with = "http_serde::header_map")]
+    pub headers: HeaderMap,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl MirrorEntry {
+    /// Creates a new MirrorEntry, whose file is set when it is added to the manifest.
+    pub fn new(status: Option<u16>, headers: Option<&HeaderMap>, truncated: bool) -> Self {
+        MirrorEntry {
+            file: String::new(),
+            status,
+            headers: headers.cloned().unwrap_or_default(),
+            truncated,
+        }
+    }
+}
+
+/// Files and folders taken in the mirror, so no two urls are saved to the same path
+/// and no file is saved where a folder is needed.
+#[derive(Debug, Default)]
+struct Paths {
+    files: HashMap<PathBuf, String>,
+    folders: HashSet<PathBuf>,
+}
+
+impl Paths {
+    /// Function to take the path of a file for an url.
+    /// Returns false if it collides with the file of another url or with a folder.
+    fn take(&mut self, url: &Url, file: &Path) -> bool {
+        if self.files.get(file).is_some_and(|x| x != url.as_str())
+            || self.folders.contains(file)
+            || file.ancestors().skip(1).any(|x| self.files.contains_key(x))
+        {
+            return false;
+        }
+        self.files.insert(file.to_path_buf(), url.to_string());
+        for x in file.ancestors().skip(1) {
+            self.folders.insert(x.to_path_buf());
+        }
+        true
+    }
+}
+
+/// Mirror of the crawled pages in a folder.
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    folder: PathBuf,
+    mime_types: Vec<String>,
+    entries: Arc<Mutex<BTreeMap<String, MirrorEntry>>>,
+    paths: Arc<Mutex<Paths>>,
+}
+
+impl Mirror {
+    /// Creates a new Mirror in the folder.
+    /// Only bodies of the mime types are saved if any are given,
+    /// like text/html, or image or image/* for all images.
+    pub fn new(folder: PathBuf, mime_types: Vec<String>) -> Self {
+        Mirror {
+            folder,
+            mime_types: mime_types.iter().map(|x| x.to_lowercase()).collect(),
+            entries: Arc::default(),
+            paths: Arc::default(),
+        }
+    }
+
+    /// Function to check if bodies of a mime type are saved.
+    pub fn accepts(&self, mime: Option<&Mime>) -> bool {
+        if self.mime_types.is_empty() {
+            return true;
+        }
+        let mime = match mime {
+            Some(x) => x,
+            None => return false,
+        };
+        let essence = mime.essence_str().to_lowercase();
+        let type_ = mime.type_().as_str().to_lowercase();
+        self.mime_types
+            .iter()
+            .any(|x| *x == essence || *x == type_ || x.strip_suffix("/*") == Some(type_.as_str()))
+    }
+
+    /// Function to save the body of a page, if its mime type is saved.
+    /// Urls whose path collides with another saved url are saved under their hash.
+    pub async fn save(
+        &self,
+        url: &Url,
+        mime: Option<&Mime>,
+        entry: MirrorEntry,
+        body: &[u8],
+    ) -> Result<(), std::io::Error> {
+        if !self.accepts(mime) {
+            return Ok(());
+        }
+        let file = self.take_path(url, is_html(mime));
+        let path = self.folder.join(&file);
+        if let Some(x) = path.parent() {
+            tokio::fs::create_dir_all(x).await?;
+        }
+        tokio::fs::write(path, body).await?;
+        self.add(url, file, entry);
+        Ok(())
+    }
+
+    /// Function to keep the file saved for a page by the last crawl,
+    /// when the page was not modified since then.
+    pub fn keep(&self, url: &Url, entry: MirrorEntry) {
+        let file = [true, false]
+            .iter()
+            .flat_map(|x| vec![local_path(url, *x), collision_path(url, *x)])
+            .find(|x| self.folder.join(x).is_file());
+        if let Some(x) = file {
+            if let Ok(mut paths) = self.paths.lock() {
+                paths.take(url, &x);
+            }
+            self.add(url, x, entry);
+        }
+    }
+
+    /// Function to take the path of the file of an url, or its collision path if it is taken.
+    fn take_path(&self, url: &Url, html: bool) -> PathBuf {
+        let file = local_path(url, html);
+        let mut paths = match self.paths.lock() {
+            Ok(x) => x,
+            Err(_) => return file,
+        };
+        if paths.take(url, &file) {
+            return file;
+        }
+        let file = collision_path(url, html);
+        paths.take(url, &file);
+        file
+    }
+
+    /// Function to add an url to the manifest.
+    fn add(&self, url: &Url, file: PathBuf, mut entry: MirrorEntry) {
+        entry.file = file.to_string_lossy().replace('\\', "/");
+        if let Ok(mut x) = self.entries.lock() {
+            x.insert(url.to_string(), entry);
+        }
+    }
+
+    /// Function to rewrite the links between the saved html pages and to write the manifest.
+    /// Returns the number of saved urls.
+    pub async fn finish(&self) -> Result<usize, std::io::Error> {
+        let entries = match self.entries.lock() {
+            Ok(x) => x.clone(),
+            Err(_) => BTreeMap::new(),
+        };
+        let files: BTreeMap<&str, &str> = entries
+            .iter()
+            .map(|(url, x)| (url.as_str(), x.file.as_str()))
+            .collect();
+        for (url, entry) in entries.iter() {
+            if !entry.file.ends_with(".html") && !entry.file.ends_with(".htm") {
+                continue;
+            }
+            let (path, url) = match Url::parse(url) {
+                Ok(x) => (self.folder.join(&entry.file), x),
+                Err(_) => continue,
+            };
+            let html = match tokio::fs::read(&path).await {
+                Ok(x) => String::from_utf8_lossy(&x).into_owned(),
+                Err(_) => continue,
+            };
+            let rewritten = rewrite_links(&html, &url, Path::new(&entry.file), &files);
+            if rewritten != html {
+                tokio::fs::write(&path, rewritten).await?;
+            }
+        }
+        tokio::fs::create_dir_all(&self.folder).await?;
+        let json = serde_json::to_vec_pretty(&entries)?;
+        tokio::fs::write(self.folder.join(MANIFEST_FILE_NAME), json).await?;
+        Ok(entries.len())
+    }
+}
+
+/// Function to check if a mime type is html.
+fn is_html(mime: Option<&Mime>) -> bool {
+    mime.is_some_and(|x| {
+        matches!(
+            (x.type_().as_str(), x.subtype().as_str()),
+            ("text", "html") | ("application", "xhtml")
+        )
+    })
+}
+
+/// Function to get the path of the file of an url in the mirror.
+/// Html pages are saved as index.html in the folder of their path, unless the path
+/// already names a file, which then gets .html appended if it is not a html file.
+pub fn local_path(url: &Url, html: bool) -> PathBuf {
+    let mut path = PathBuf::new();
+    match url.port() {
+        Some(x) => path.push(format!("{}_{}", url.host_str().unwrap_or("_"), x)),
+        None => path.push(url.host_str().unwrap_or("_")),
+    }
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|x| x.filter(|x| !x.is_empty()).collect())
+        .unwrap_or_default();
+    for x in &segments {
+        path.push(x.replace(['\\', ':'], "_"));
+    }
+    let last = segments.last().copied().unwrap_or_default();
+    if segments.is_empty() || url.path().ends_with('/') || (html && !last.contains('.')) {
+        path.push("index.html");
+    } else if html && !last.ends_with(".html") && !last.ends_with(".htm") {
+        path.set_file_name(format!("{}.html", last.replace(['\\', ':'], "_")));
+    }
+    if let Some(query) = url.query() {
+        let name = path
+            .file_stem()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let hash = format!("{}@{:08x}", name, fnv1a(query.as_bytes()) as u32);
+        match path.extension().map(|x| x.to_string_lossy().into_owned()) {
+            Some(x) => path.set_file_name(format!("{}.{}", hash, x)),
+            None => path.set_file_name(hash),
+        }
+    }
+    path
+}
+
+/// Function to get the path of the file of an url whose own path is taken by another url,
+/// named by the hash of the url in the folder of its host.
+fn collision_path(url: &Url, html: bool) -> PathBuf {
+    let path = local_path(url, html);
+    let name = format!("@{:016x}", fnv1a(url.as_str().as_bytes()));
+    let mut file = path.iter().take(1).collect::<PathBuf>();
+    match path.extension() {
+        Some(x) => file.push(format!("{}.{}", name, x.to_string_lossy())),
+        None => file.push(name),
+    }
+    file
+}
+
+/// Function to get the relative path from a file to another one in the mirror.
+fn relative_path(from: &Path, to: &Path) -> String {
+    let from: Vec<Component> = from
+        .parent()
+        .map(|x| x.components().collect())
+        .unwrap_or_default();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(x, y)| x == y).count();
+    let mut parts: Vec<String> = vec!["..".to_string(); from.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|x| x.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+/// Function to rewrite the href and src attributes of a html page which point to saved urls.
+fn rewrite_links(html: &str, url: &Url, file: &Path, files: &BTreeMap<&str, &str>) -> String {
+    static ATTRIBUTE_REGEX: OnceLock<Regex> = OnceLock::new();
+    let attribute_regex = ATTRIBUTE_REGEX.get_or_init(|| {
+        Regex::new(r#"(?i)\b(href|src)(\s*=\s*)("[^"]*"|'[^']*')"#).expect("valid attribute regex")
+    });
+    attribute_regex
+        .replace_all(html, |x: &Captures| {
+            let quoted = &x[3];
+            let value = &quoted[1..quoted.len() - 1];
+            let mut target = match url.join(value) {
+                Ok(y) => y,
+                Err(_) => return x[0].to_string(),
+            };
+            let fragment = target.fragment().map(|y| format!("#{}", y));
+            target.set_fragment(None);
+            match files.get(target.as_str()) {
+                Some(y) => format!(
+                    "{}{}\"{}{}\"",
+                    &x[1],
+                    &x[2],
+                    relative_path(file, Path::new(y)),
+                    fragment.unwrap_or_default()
+                ),
+                None => x[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(x: &str) -> Url {
+        Url::parse(&format!("https://example.com{}", x)).unwrap()
+    }
+
+    #[test]
+    fn mirror_paths() {
+        let path = |x: &str, html| local_path(&url(x), html).to_string_lossy().into_owned();
+        assert_eq!(path("/", true), "example.com/index.html");
+        assert_eq!(path("/docs/", true), "example.com/docs/index.html");
+        assert_eq!(
+            path("/docs/intro", true),
+            "example.com/docs/intro/index.html"
+        );
+        assert_eq!(path("/page.html", true), "example.com/page.html");
+        assert_eq!(path("/page.php", true), "example.com/page.php.html");
+        assert_eq!(path("/logo.png", false), "example.com/logo.png");
+        assert!(path("/search?q=1", true).starts_with("example.com/search/index@"));
+        assert_ne!(path("/search?q=1", true), path("/search?q=2", true));
+        assert_eq!(
+            local_path(&Url::parse("http://127.0.0.1:8080/a").unwrap(), false),
+            PathBuf::from("127.0.0.1_8080/a")
+        );
+
+        assert_eq!(
+            relative_path(
+                Path::new("example.com/docs/intro/index.html"),
+                Path::new("example.com/logo.png")
+            ),
+            "../../logo.png"
+        );
+        assert_eq!(
+            relative_path(
+                Path::new("example.com/index.html"),
+                Path::new("example.com/docs/index.html")
+            ),
+            "docs/index.html"
+        );
+    }
+
+    #[test]
+    fn rewrite_saved_links() {
+        let mut files = BTreeMap::new();
+        files.insert("https://example.com/docs", "example.com/docs/index.html");
+        files.insert("https://example.com/logo.png", "example.com/logo.png");
+        let html = r#"<a href="/docs#top">Docs</a><img src='logo.png'><a href="/other">x</a>"#;
+        let rewritten = rewrite_links(html, &url("/"), Path::new("example.com/index.html"), &files);
+        assert_eq!(
+            rewritten,
+            r#"<a href="docs/index.html#top">Docs</a><img src="logo.png"><a href="/other">x</a>"#
+        );
+
+        let mirror = Mirror::new(
+            PathBuf::new(),
+            vec!["text/html".to_string(), "image/*".to_string()],
+        );
+        assert!(
+            Mirror::new(PathBuf::new(), vec!["image".to_string()]).accepts(Some(&mime::IMAGE_PNG))
+        );
+        assert!(mirror.accepts(Some(&"text/html; charset=utf-8".parse().unwrap())));
+        assert!(mirror.accepts(Some(&mime::IMAGE_PNG)));
+        assert!(!mirror.accepts(Some(&mime::APPLICATION_PDF)));
+        assert!(!mirror.accepts(None));
+        assert!(Mirror::new(PathBuf::new(), Vec::new()).accepts(None));
+    }
+
+    #[tokio::test]
+    async fn path_collisions() {
+        let folder = std::env::temp_dir().join(format!("crate-collisions-{}", std::process::id()));
+        let mirror = Mirror::new(folder.clone(), Vec::new());
+        let html = Some(&mime::TEXT_HTML);
+        let save = |x: &'static str, mime| {
+            let mirror = mirror.clone();
+            async move {
+                let entry = MirrorEntry::new(Some(200), None, false);
+                mirror.save(&url(x), mime, entry, x.as_bytes()).await
+            }
+        };
+        save("/a", Some(&mime::APPLICATION_OCTET_STREAM))
+            .await
+            .unwrap();
+        save("/a/b", html).await.unwrap();
+        save("/page.php", html).await.unwrap();
+        save("/page.html", html).await.unwrap();
+        save("/page.html", html).await.unwrap();
+        let entries = mirror.entries.lock().unwrap().clone();
+        let read =
+            |x: &str| std::fs::read_to_string(folder.join(&entries[&url(x).to_string()].file));
+        let contents: Vec<String> = ["/a", "/a/b", "/page.php", "/page.html"]
+            .iter()
+            .map(|x| read(x).unwrap_or_default())
+            .collect();
+        let _ = std::fs::remove_dir_all(&folder);
+
+        assert_eq!(contents, vec!["/a", "/a/b", "/page.php", "/page.html"]);
+        assert!(entries[&url("/a/b").to_string()]
+            .file
+            .starts_with("example.com/@"));
+        assert_eq!(
+            entries[&url("/page.html").to_string()].file,
+            "example.com/page.html"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+/*!
+Module containing the network policy of the crawler.
+Used to keep the crawler away from private networks when crawling untrusted urls.
+*/
+use ipnet::IpNet;
+use std::{error::Error, fmt, net::IpAddr};
+
+/// Maximum number of redirects followed for a request.
+const MAX_REDIRECTS: usize = 10;
+
+/// Ranges which are refused when private addresses are blocked.
+const PRIVATE_RANGES: &[(&str, &str)] = &[
+    ("0.0.0.0/8", "unspecified"),
+    ("10.0.0.0/8", "private"),
+    ("100.64.0.0/10", "shared"),
+    ("127.0.0.0/8", "loopback"),
+    ("169.254.0.0/16", "link-local"),
+    ("172.16.0.0/12", "private"),
+    ("192.168.0.0/16", "private"),
+    ("224.0.0.0/4", "multicast"),
+    ("255.255.255.255/32", "broadcast"),
+    ("::/128", "unspecified"),
+    ("::1/128", "loopback"),
+    ("64:ff9b::/96", "nat64"),
+    ("fc00::/7", "private"),
+    ("fe80::/10", "link-local"),
+    ("ff00::/8", "multicast"),
+];
+
+/// Error for a connection refused by the network policy.
+/// Contains the reason for the refusal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Refused(pub String);
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for Refused {}
+
+/// Network policy deciding which addresses may be connected to.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkPolicy {
+    ranges: Vec<(IpNet, String)>,
+}
+
+impl NetworkPolicy {
+    /// Creates a new NetworkPolicy.
+    /// Refuses private, loopback and link-local addresses if block_private is set,
+    /// and always refuses the given ranges.
+    pub fn new(block_private: bool, blocked_ranges: &[IpNet]) -> Self {
+        let mut ranges: Vec<(IpNet, String)> = Vec::new();
+        if block_private {
+            ranges.extend(
+                PRIVATE_RANGES
+                    .iter()
+                    .filter_map(|(net, reason)| Some((net.parse().ok()?, reason.to_string()))),
+            );
+        }
+        ranges.extend(
+            blocked_ranges
+                .iter()
+                .map(|x| (*x, format!("blocked range {}", x))),
+        );
+        NetworkPolicy { ranges }
+    }
+
+    /// Function to check if the policy refuses anything at all.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Function to check if an address may be connected to.
+    pub fn check_ip(&self, ip: IpAddr) -> Result<(), Refused> {
+        let ip = match ip {
+            IpAddr::V6(x) => match x.to_ipv4_mapped() {
+                Some(x) => IpAddr::V4(x),
+                None => ip,
+            },
+            IpAddr::V4(_) => ip,
+        };
+        match self.ranges.iter().find(|(net, _)| net.contains(&ip)) {
+            Some((_, reason)) => Err(Refused(format!("Refused {} address {}", reason, ip))),
+            None => Ok(()),
+        }
+    }
+
+    /// Function to check a host which is an address.
+    /// Domains are checked when they are resolved.
+    pub fn check_host(&self, host: &url::Host) -> Result<(), Refused> {
+        match host {
+            url::Host::Ipv4(x) => self.check_ip(IpAddr::V4(*x)),
+            url::Host::Ipv6(x) => self.check_ip(IpAddr::V6(*x)),
+            url::Host::Domain(_) => Ok(()),
+        }
+    }
+
+    /// Function to get the addresses which may be connected to.
+    /// Returns the reason for the first refused address if none are left.
+    pub fn filter_ips(&self, ips: Vec<IpAddr>) -> Result<Vec<IpAddr>, Refused> {
+        let mut refused = None;
+        let allowed: Vec<IpAddr> = ips
+            .into_iter()
+            .filter(|x| match self.check_ip(*x) {
+                Ok(_) => true,
+                Err(e) => {
+                    refused.get_or_insert(e);
+                    false
+                }
+            })
+            .collect();
+        match (allowed.is_empty(), refused) {
+            (true, Some(e)) => Err(e),
+            _ => Ok(allowed),
+        }
+    }
+
+    /// Function to get a redirect policy which checks every redirect against the network policy.
+    pub fn redirect_policy(&self) -> reqwest::redirect::Policy {
+        let policy = self.clone();
+        reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > MAX_REDIRECTS {
+                return attempt.error("Too many redirects");
+            }
+            let checked = match attempt.url().host() {
+                Some(x) => policy.check_host(&x.to_owned()),
+                None => Ok(()),
+            };
+            match checked {
+                Ok(_) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        })
+    }
+}
+
+/// Function to find the refusal of the network policy in an error chain.
+pub fn find_refusal(error: &(dyn Error + 'static)) -> Option<Refused> {
+    let mut current = Some(error);
+    while let Some(x) = current {
+        if let Some(refused) = x.downcast_ref::<Refused>() {
+            return Some(refused.clone());
+        }
+        current = x.source();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_addresses() {
+        let policy = NetworkPolicy::new(true, &["203.0.113.0/24".parse().unwrap()]);
+        let refused = |x: &str| policy.check_ip(x.parse().unwrap()).is_err();
+
+        assert!(refused("169.254.169.254"));
+        assert!(refused("10.0.0.5"));
+        assert!(refused("127.0.0.1"));
+        assert!(refused("::1"));
+        assert!(refused("::ffff:192.168.1.1"));
+        assert!(refused("fe80::1"));
+        assert!(refused("64:ff9b::a9fe:a9fe"));
+        assert!(refused("239.255.255.250"));
+        assert!(refused("255.255.255.255"));
+        assert!(refused("ff02::1"));
+        assert!(refused("203.0.113.7"));
+        assert!(!refused("93.184.216.34"));
+        assert!(!refused("2606:2800:220:1::1"));
+
+        let ips = vec![
+            "10.0.0.1".parse().unwrap(),
+            "93.184.216.34".parse().unwrap(),
+        ];
+        assert_eq!(policy.filter_ips(ips).unwrap().len(), 1);
+        let ips = vec!["10.0.0.1".parse().unwrap()];
+        assert_eq!(
+            policy.filter_ips(ips),
+            Err(Refused("Refused private address 10.0.0.1".to_string()))
+        );
+
+        assert!(NetworkPolicy::new(false, &[]).is_empty());
+    }
+}
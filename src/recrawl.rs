@@ -0,0 +1,279 @@
+/*!
+Module containing the conditional re-crawl of the pages of the last crawl.
+*/
+use crate::extractors::content::fnv1a;
+use crate::extractors::words::{SearchOptions, WordHit};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// What is kept of a crawled page for the next crawl.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PageRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Url>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub word_hits: Vec<WordHit>,
+}
+
+impl PageRecord {
+    /// Creates a new PageRecord with the validators of the response headers.
+    pub fn new(headers: Option<&HeaderMap>) -> Self {
+        let value = |name| {
+            headers
+                .and_then(|x| x.get(name))
+                .and_then(|x: &HeaderValue| x.to_str().ok())
+                .map(|x| x.to_string())
+        };
+        PageRecord {
+            etag: value(header::ETAG),
+            last_modified: value(header::LAST_MODIFIED),
+            ..PageRecord::default()
+        }
+    }
+
+    /// Function to check if the page changed since it was recorded.
+    /// Pages are compared by the hash of their content, or by their validators without it.
+    fn changed_since(&self, previous: &PageRecord) -> bool {
+        match (&self.content_hash, &previous.content_hash) {
+            (Some(x), Some(y)) => x != y,
+            _ => self.etag != previous.etag || self.last_modified != previous.last_modified,
+        }
+    }
+}
+
+/// Pages kept for the next crawl, with the hash of the search settings of their word hits.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PageFile {
+    #[serde(default)]
+    pub settings: String,
+    #[serde(default)]
+    pub pages: BTreeMap<String, PageRecord>,
+}
+
+/// Function to get the hash of the search words, the rules and the search options.
+/// Word hits of the last crawl are only reused if it had the same hash.
+pub fn settings_hash(words: &HashSet<String>, rules: &[String], options: &SearchOptions) -> String {
+    let mut words: Vec<&String> = words.iter().collect();
+    words.sort();
+    let settings = format!(
+        "{:?}\0{:?}\0{} {} {}",
+        words, rules, options.ignore_case, options.whole_word, options.search_markup
+    );
+    format!("{:016x}", fnv1a(settings.as_bytes()))
+}
+
+/// Status of a page compared to the last crawl.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageStatus {
+    New,
+    Changed,
+    Unchanged,
+}
+
+/// Pages of this crawl compared to the last one, written at the end of the crawl.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RecrawlReport {
+    pub new: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub gone: Vec<String>,
+}
+
+/// Pages of the last crawl and of this one.
+/// Pages are only compared to the last crawl and kept for the next one when re-crawling.
+#[derive(Clone, Debug, Default)]
+pub struct Recrawl {
+    enabled: bool,
+    settings: String,
+    stale: bool,
+    previous: Arc<HashMap<String, PageRecord>>,
+    current: Arc<Mutex<BTreeMap<String, (PageRecord, PageStatus)>>>,
+}
+
+impl Recrawl {
+    /// Creates a new Recrawl from the pages of the last crawl, with the hash of the search settings.
+    /// Pages of the last crawl are requested again unconditionally if its settings were different.
+    pub fn new(previous: PageFile, settings: String) -> Self {
+        Recrawl {
+            enabled: true,
+            stale: previous.settings != settings,
+            settings,
+            previous: Arc::new(previous.pages.into_iter().collect()),
+            current: Arc::default(),
+        }
+    }
+
+    /// Function to check if the pages are compared to the last crawl.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Function to get the page of the last crawl.
+    pub fn previous(&self, url: &Url) -> Option<&PageRecord> {
+        self.previous.get(url.as_str())
+    }
+
+    /// Function to get the headers of the conditional request for a page of the last crawl.
+    pub fn conditional_headers(&self, url: &Url) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if self.stale {
+            return headers;
+        }
+        if let Some(x) = self.previous(url) {
+            if let Some(Ok(x)) = x.etag.as_deref().map(HeaderValue::from_str) {
+                headers.insert(header::IF_NONE_MATCH, x);
+            }
+            if let Some(Ok(x)) = x.last_modified.as_deref().map(HeaderValue::from_str) {
+                headers.insert(header::IF_MODIFIED_SINCE, x);
+            }
+        }
+        headers
+    }
+
+    /// Function to record a crawled page, if re-crawling.
+    /// Returns the status of the page compared to the last crawl.
+    pub fn record(&self, url: &Url, record: PageRecord) -> Option<PageStatus> {
+        if !self.enabled {
+            return None;
+        }
+        let status = match self.previous(url) {
+            None => PageStatus::New,
+            Some(x) if record.changed_since(x) => PageStatus::Changed,
+            Some(_) => PageStatus::Unchanged,
+        };
+        if let Ok(mut x) = self.current.lock() {
+            x.insert(url.to_string(), (record, status));
+        }
+        Some(status)
+    }
+
+    /// Function to get the pages of this crawl, sorted by url, with the search settings.
+    pub fn pages(&self) -> PageFile {
+        let pages = match self.current.lock() {
+            Ok(x) => x
+                .iter()
+                .map(|(url, (record, _))| (url.clone(), record.clone()))
+                .collect(),
+            Err(_) => BTreeMap::new(),
+        };
+        PageFile {
+            settings: self.settings.clone(),
+            pages,
+        }
+    }
+
+    /// Function to get the pages of this crawl compared to the last one.
+    pub fn report(&self) -> RecrawlReport {
+        let mut report = RecrawlReport::default();
+        let current = match self.current.lock() {
+            Ok(x) => x,
+            Err(_) => return report,
+        };
+        for (url, (_, status)) in current.iter() {
+            match status {
+                PageStatus::New => report.new.push(url.clone()),
+                PageStatus::Changed => report.changed.push(url.clone()),
+                PageStatus::Unchanged => report.unchanged.push(url.clone()),
+            }
+        }
+        report.gone = self
+            .previous
+            .keys()
+            .filter(|x| !current.contains_key(*x))
+            .cloned()
+            .collect();
+        report.gone.sort();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(x: &str) -> Url {
+        Url::parse(&format!("https://example.com/{}", x)).unwrap()
+    }
+
+    #[test]
+    fn compare_pages() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+        let record = PageRecord::new(Some(&headers));
+        assert_eq!(record.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(record.last_modified, None);
+        let hashed = |x: &str| PageRecord {
+            content_hash: Some(x.to_string()),
+            ..record.clone()
+        };
+        assert!(!hashed("1").changed_since(&hashed("1")));
+        assert!(hashed("1").changed_since(&hashed("2")));
+        assert!(!record.changed_since(&hashed("2")));
+
+        let mut previous = PageFile {
+            settings: "s".to_string(),
+            pages: BTreeMap::new(),
+        };
+        for x in &["a", "b", "c"] {
+            previous.pages.insert(url(x).to_string(), record.clone());
+        }
+        let stale = Recrawl::new(previous.clone(), "t".to_string());
+        assert!(stale.conditional_headers(&url("a")).is_empty());
+        let recrawl = Recrawl::new(previous, "s".to_string());
+        let conditional = recrawl.conditional_headers(&url("a"));
+        assert_eq!(conditional[header::IF_NONE_MATCH], "\"v1\"");
+        assert!(!conditional.contains_key(header::IF_MODIFIED_SINCE));
+        assert!(recrawl.conditional_headers(&url("new")).is_empty());
+
+        assert_eq!(
+            recrawl.record(&url("a"), record.clone()),
+            Some(PageStatus::Unchanged)
+        );
+        assert_eq!(
+            recrawl.record(&url("b"), PageRecord::default()),
+            Some(PageStatus::Changed)
+        );
+        assert_eq!(
+            recrawl.record(&url("new"), PageRecord::default()),
+            Some(PageStatus::New)
+        );
+        let report = recrawl.report();
+        assert_eq!(report.unchanged, vec![url("a").to_string()]);
+        assert_eq!(report.changed, vec![url("b").to_string()]);
+        assert_eq!(report.new, vec![url("new").to_string()]);
+        assert_eq!(report.gone, vec![url("c").to_string()]);
+        assert_eq!(recrawl.pages().pages.len(), 3);
+        assert_eq!(recrawl.pages().settings, "s");
+
+        let recrawl = Recrawl::default();
+        assert_eq!(recrawl.record(&url("a"), PageRecord::default()), None);
+        assert!(recrawl.pages().pages.is_empty());
+    }
+
+    #[test]
+    fn search_settings() {
+        let words: HashSet<String> = vec!["a".to_string(), "b".to_string()].into_iter().collect();
+        let options = SearchOptions::default();
+        let hash = settings_hash(&words, &[], &options);
+        assert_eq!(hash, settings_hash(&words, &[], &options));
+        assert_ne!(
+            hash,
+            settings_hash(&words, &["x: \"a\"".to_string()], &options)
+        );
+        let ignore_case = SearchOptions {
+            ignore_case: true,
+            ..SearchOptions::default()
+        };
+        assert_ne!(hash, settings_hash(&words, &[], &ignore_case));
+    }
+}
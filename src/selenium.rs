@@ -0,0 +1,947 @@
+/*!
+Module containing the Selenium part of the crawler.
+Takes screenshots of the flagged pages and renders pages through a WebDriver server.
+*/
+use crate::extractors::{content, links::Link};
+use crate::file_handler;
+use crate::policy::NetworkPolicy;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use thirtyfour::error::WebDriverError;
+use thirtyfour::extensions::chrome::ChromeDevTools;
+use thirtyfour::prelude::*;
+use thirtyfour::{ExtensionCommand, OptionRect, RequestMethod};
+use tokio::{
+    fs,
+    sync::{mpsc, Mutex, Semaphore},
+    task::JoinHandle,
+    time,
+};
+
+/// Time a session gets to answer after a failed page before it is replaced.
+const ALIVE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Number of tries to start a session replacing a crashed one.
+const SESSION_RETRIES: u32 = 3;
+/// Time before the second try to start a session, doubled for every further try.
+const SESSION_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Maximum length of the url part of a screenshot name.
+const MAX_NAME_LENGTH: usize = 100;
+/// Time between the checks for network idle.
+const NETWORK_IDLE_POLL: Duration = Duration::from_millis(500);
+/// Maximum height of the window when it is resized for a full page screenshot.
+const MAX_PAGE_HEIGHT: i32 = 16384;
+
+/// Browser used for the WebDriver session.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Browser {
+    Chrome,
+    Firefox,
+}
+
+impl FromStr for Browser {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chrome" | "chromium" => Ok(Browser::Chrome),
+            "firefox" => Ok(Browser::Firefox),
+            _ => Err(format!("Unknown browser {}", s)),
+        }
+    }
+}
+
+/// Size of the browser window, written as `WIDTHxHEIGHT`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for WindowSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = s
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)));
+        match size {
+            Some((width, height)) if width > 0 && height > 0 => Ok(WindowSize { width, height }),
+            _ => Err(format!("Invalid window size {}", s)),
+        }
+    }
+}
+
+/// Options for the WebDriver session.
+#[derive(Clone, Debug)]
+pub struct SeleniumOptions {
+    pub webdriver_url: String,
+    pub browser: Browser,
+    pub headless: bool,
+    pub window_size: Option<WindowSize>,
+    pub browser_args: Vec<String>,
+    pub page_load_timeout: u64,
+    pub sessions: usize,
+    pub screenshot_timeout: u64,
+    pub full_page: bool,
+    pub save_dom: bool,
+    pub policy: NetworkPolicy,
+}
+
+impl SeleniumOptions {
+    /// Function to start a new WebDriver session with the options applied.
+    /// Chrome always gets `--enable-automation` before the extra browser args.
+    async fn new_session(&self) -> WebDriverResult<WebDriver> {
+        let capabilities = match self.browser {
+            Browser::Chrome => {
+                let mut caps = DesiredCapabilities::chrome();
+                caps.add_chrome_arg("--enable-automation")?;
+                if self.headless {
+                    caps.set_headless()?;
+                }
+                for x in &self.browser_args {
+                    caps.add_chrome_arg(x)?;
+                }
+                serde_json::to_value(caps)?
+            }
+            Browser::Firefox => {
+                let mut caps = DesiredCapabilities::firefox();
+                if self.headless {
+                    caps.set_headless()?;
+                }
+                for x in &self.browser_args {
+                    caps.add_firefox_arg(x)?;
+                }
+                serde_json::to_value(caps)?
+            }
+        };
+        let driver = WebDriver::new(&self.webdriver_url, capabilities).await?;
+        driver
+            .set_page_load_timeout(Duration::from_secs(self.page_load_timeout))
+            .await?;
+        if let Some(x) = self.window_size {
+            driver
+                .set_window_rect(
+                    OptionRect::new()
+                        .with_width(x.width as i32)
+                        .with_height(x.height as i32),
+                )
+                .await?;
+        }
+        Ok(driver)
+    }
+}
+
+/// Function to handle selenium.
+/// Takes a screenshot of every url received, if selenium is enabled and there is an output folder.
+/// The urls are shared between a pool of sessions, each taking one page at a time.
+pub async fn handle_selenium(
+    file_path: Option<PathBuf>,
+    options: Option<SeleniumOptions>,
+    rx: mpsc::Receiver<Link>,
+) -> Result<(), WebDriverError> {
+    if let (Some(options), Some(file_path)) = (options, file_path) {
+        let mut file_path = file_path.clone();
+        file_path.push("screenshots");
+        if fs::create_dir_all(&file_path).await.is_err() {
+            return Err(WebDriverError::FatalError(
+                "Could not create the screenshots folder".to_string(),
+            ));
+        }
+
+        let pool = Arc::new(SessionPool {
+            options,
+            folder: file_path,
+            rx: Mutex::new(rx),
+            records: Mutex::new(Vec::new()),
+            abandoned: Mutex::new(Vec::new()),
+        });
+        let workers = (0..pool.options.sessions.max(1)).map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(pool.run_session())
+        });
+        let results = futures::future::join_all(workers).await;
+        let abandoned = std::mem::take(&mut *pool.abandoned.lock().await);
+        futures::future::join_all(abandoned).await;
+
+        let mut records = std::mem::take(&mut *pool.records.lock().await);
+        records.sort_by(|a, b| a.file.cmp(&b.file));
+        file_handler::write_json_file(pool.folder.clone(), file_handler::INDEX_FILE_NAME, &records)
+            .await?;
+        for x in results {
+            match x {
+                Ok(x) => x?,
+                Err(e) => return Err(WebDriverError::FatalError(e.to_string())),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sessions taking screenshots of the urls from the selenium channel.
+struct SessionPool {
+    options: SeleniumOptions,
+    folder: PathBuf,
+    rx: Mutex<mpsc::Receiver<Link>>,
+    records: Mutex<Vec<ScreenshotRecord>>,
+    abandoned: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl SessionPool {
+    /// Function to run one session of the pool until the channel is closed.
+    /// A session which crashed is replaced by a new one.
+    /// So is a session which took longer than the screenshot timeout,
+    /// which is left to finish the page in the background without saving it and closed afterwards.
+    async fn run_session(self: Arc<Self>) -> Result<(), WebDriverError> {
+        let mut driver = Some(self.options.new_session().await?);
+        loop {
+            let link = match self.rx.lock().await.recv().await {
+                Some(x) => x,
+                None => break,
+            };
+            let refused = link.refused.clone().or_else(|| {
+                let host = link.host.as_ref()?;
+                self.options.policy.check_host(host).err().map(|x| x.0)
+            });
+            if let Some(x) = refused {
+                println!("Error : Skipped the screenshot of {} : {}", link.url, x);
+                continue;
+            }
+            let current = match driver.take() {
+                Some(x) => x,
+                None => match self.replace_session().await {
+                    Some(x) => x,
+                    None => {
+                        println!("Error : Gave up the screenshot of {}", link.url);
+                        continue;
+                    }
+                },
+            };
+            let pool = self.clone();
+            let abandoned = Arc::new(AtomicBool::new(false));
+            let abandoned_clone = abandoned.clone();
+            let mut page = tokio::spawn(async move {
+                let result = pool.screenshot(&current, &link, &abandoned_clone).await;
+                (current, result)
+            });
+            let timeout = Duration::from_secs(self.options.screenshot_timeout);
+            match time::timeout(timeout, &mut page).await {
+                Ok(Ok((current, Ok(_)))) => driver = Some(current),
+                Ok(Ok((current, Err(_)))) => {
+                    if is_alive(&current).await {
+                        driver = Some(current);
+                    } else {
+                        close_session(current).await;
+                    }
+                }
+                Ok(Err(e)) => return Err(WebDriverError::FatalError(e.to_string())),
+                Err(_) => {
+                    abandoned.store(true, Ordering::SeqCst);
+                    self.abandoned.lock().await.push(tokio::spawn(async move {
+                        if let Ok((x, _)) = page.await {
+                            close_session(x).await;
+                        }
+                    }));
+                }
+            }
+        }
+        if let Some(x) = driver {
+            close_session(x).await;
+        }
+        Ok(())
+    }
+
+    /// Function to start a session replacing a crashed one.
+    /// Failed tries are logged and tried again after a growing delay.
+    async fn replace_session(&self) -> Option<WebDriver> {
+        let mut delay = SESSION_RETRY_DELAY;
+        for attempt in 1..=SESSION_RETRIES {
+            match self.options.new_session().await {
+                Ok(x) => return Some(x),
+                Err(e) => println!("Error : Could not start a WebDriver session : {}", e),
+            }
+            if attempt < SESSION_RETRIES {
+                time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+        None
+    }
+
+    /// Function to take a screenshot of a page and record it in the index.
+    /// The window is made fullscreen before the screenshot unless a window size is given.
+    /// Nothing is written once the page was abandoned for taking too long.
+    async fn screenshot(
+        &self,
+        driver: &WebDriver,
+        link: &Link,
+        abandoned: &AtomicBool,
+    ) -> WebDriverResult<()> {
+        driver.get(link.url.as_str()).await?;
+        if self.options.window_size.is_none() {
+            let _ = driver.fullscreen_window().await;
+        }
+        let png = if self.options.full_page {
+            full_page_png(driver, self.options.browser).await?
+        } else {
+            driver.screenshot_as_png().await?
+        };
+        if abandoned.load(Ordering::SeqCst) {
+            return Err(WebDriverError::FatalError(format!(
+                "Abandoned the screenshot of {}",
+                link.url
+            )));
+        }
+        let file = screenshot_name(&link.url);
+        fs::write(self.folder.join(&file), png).await?;
+        let dom = if self.options.save_dom {
+            let dom = format!("{}.html", file.strip_suffix(".png").unwrap_or(&file));
+            fs::write(self.folder.join(&dom), driver.page_source().await?).await?;
+            Some(dom)
+        } else {
+            None
+        };
+
+        let record = ScreenshotRecord {
+            file,
+            dom,
+            url: link.url.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |x| x.as_secs()),
+            words: link.word_hits.iter().map(|x| x.term.clone()).collect(),
+            rules: link.matched_rules.clone(),
+            viewport: viewport_size(driver).await,
+            title: driver.title().await.unwrap_or_default(),
+        };
+        self.records.lock().await.push(record);
+        Ok(())
+    }
+}
+
+/// Record of a screenshot in the index.
+/// The timestamp is in seconds since the unix epoch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScreenshotRecord {
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dom: Option<String>,
+    pub url: String,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub viewport: Option<WindowSize>,
+    pub title: String,
+}
+
+/// Function to get the name of the screenshot file of a url.
+/// The host, path and query are kept readable and the hash keeps names of similar urls apart.
+fn screenshot_name(url: &url::Url) -> String {
+    let readable = &url[url::Position::BeforeHost..url::Position::AfterQuery];
+    let mut name = String::new();
+    for c in readable.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+        if name.len() >= MAX_NAME_LENGTH {
+            break;
+        }
+    }
+    let name = name.trim_matches('_');
+    format!(
+        "{}-{:016x}.png",
+        name,
+        content::fnv1a(url.as_str().as_bytes())
+    )
+}
+
+/// Function to get the size of the viewport of the page.
+async fn viewport_size(driver: &WebDriver) -> Option<WindowSize> {
+    let size = driver
+        .execute_script("return [window.innerWidth, window.innerHeight];")
+        .await
+        .ok()?
+        .convert::<(u32, u32)>()
+        .ok()?;
+    Some(WindowSize {
+        width: size.0,
+        height: size.1,
+    })
+}
+
+/// Function to take a screenshot of the whole page instead of only the viewport.
+/// Falls back to resizing the window if the capture of the browser fails.
+async fn full_page_png(driver: &WebDriver, browser: Browser) -> WebDriverResult<Vec<u8>> {
+    let native = match browser {
+        Browser::Chrome => chrome_full_page(driver).await,
+        Browser::Firefox => driver.extension_command(FirefoxFullScreenshot).await,
+    };
+    match native.ok().and_then(|x| decode_png(&x)) {
+        Some(x) => Ok(x),
+        None => resized_full_page(driver).await,
+    }
+}
+
+/// Function to take a full page screenshot with the Chrome DevTools protocol.
+/// Returns the base64 encoded png.
+async fn chrome_full_page(driver: &WebDriver) -> WebDriverResult<serde_json::Value> {
+    let dev_tools = ChromeDevTools::new(driver.session());
+    let metrics = dev_tools.execute_cdp("Page.getLayoutMetrics").await?;
+    let size = match metrics.get("cssContentSize") {
+        Some(x) => x,
+        None => &metrics["contentSize"],
+    };
+    let params = serde_json::json!({
+        "format": "png",
+        "captureBeyondViewport": true,
+        "clip": {
+            "x": 0,
+            "y": 0,
+            "width": size["width"],
+            "height": size["height"],
+            "scale": 1,
+        },
+    });
+    let screenshot = dev_tools
+        .execute_cdp_with_params("Page.captureScreenshot", params)
+        .await?;
+    Ok(screenshot["data"].clone())
+}
+
+/// Full page screenshot command of geckodriver.
+struct FirefoxFullScreenshot;
+
+impl ExtensionCommand for FirefoxFullScreenshot {
+    fn parameters_json(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    fn method(&self) -> RequestMethod {
+        RequestMethod::Get
+    }
+
+    fn endpoint(&self) -> String {
+        "/moz/screenshot/full".to_string()
+    }
+}
+
+/// Function to take a full page screenshot by making the window as big as the page.
+/// The window gets its old size back afterwards.
+async fn resized_full_page(driver: &WebDriver) -> WebDriverResult<Vec<u8>> {
+    let rect = driver.get_window_rect().await?;
+    let (width, height) = driver
+        .execute_script(
+            "return [document.documentElement.scrollWidth, document.documentElement.scrollHeight];",
+        )
+        .await?
+        .convert::<(i32, i32)>()?;
+    driver
+        .set_window_rect(
+            OptionRect::new()
+                .with_width(width.max(rect.width))
+                .with_height(height.max(rect.height).min(MAX_PAGE_HEIGHT)),
+        )
+        .await?;
+    let png = driver.screenshot_as_png().await;
+    driver
+        .set_window_rect(
+            OptionRect::new()
+                .with_width(rect.width)
+                .with_height(rect.height),
+        )
+        .await?;
+    png
+}
+
+/// Function to decode a base64 screenshot returned by the browser.
+fn decode_png(value: &serde_json::Value) -> Option<Vec<u8>> {
+    base64::decode(value.as_str()?).ok()
+}
+
+/// Loads pages in the browser and returns their rendered DOM.
+/// Sessions are started when needed and reused for the next pages.
+#[derive(Clone)]
+pub struct Renderer {
+    options: Arc<SeleniumOptions>,
+    idle: Arc<Mutex<Vec<WebDriver>>>,
+    permits: Arc<Semaphore>,
+    wait: Duration,
+}
+
+impl Renderer {
+    /// Creates a new Renderer.
+    /// Uses at most as many sessions as the options allow,
+    /// and waits at most `wait` seconds for the network to become idle.
+    pub fn new(options: SeleniumOptions, wait: u64) -> Self {
+        Renderer {
+            permits: Arc::new(Semaphore::new(options.sessions.max(1))),
+            options: Arc::new(options),
+            idle: Arc::new(Mutex::new(Vec::new())),
+            wait: Duration::from_secs(wait),
+        }
+    }
+
+    /// Function to get the rendered DOM of a page.
+    /// A session which fails a page and does not answer anymore is closed.
+    pub async fn render(&self, url: &str) -> WebDriverResult<String> {
+        let _permit = match self.permits.acquire().await {
+            Ok(x) => x,
+            Err(e) => return Err(WebDriverError::FatalError(e.to_string())),
+        };
+        let popped = self.idle.lock().await.pop();
+        let driver = match popped {
+            Some(x) => x,
+            None => self.options.new_session().await?,
+        };
+
+        let dom = match driver.get(url).await {
+            Ok(_) => {
+                wait_for_network_idle(&driver, self.wait).await;
+                driver.page_source().await
+            }
+            Err(e) => Err(e),
+        };
+        if dom.is_ok() || is_alive(&driver).await {
+            self.idle.lock().await.push(driver);
+        } else {
+            close_session(driver).await;
+        }
+        dom
+    }
+
+    /// Function to close all the sessions.
+    pub async fn close(&self) {
+        let idle = std::mem::take(&mut *self.idle.lock().await);
+        futures::future::join_all(idle.into_iter().map(close_session)).await;
+    }
+}
+
+/// Function to wait until the page is loaded and has not fetched any new resources for a while.
+/// Gives up after the maximum wait.
+async fn wait_for_network_idle(driver: &WebDriver, max_wait: Duration) {
+    let start = Instant::now();
+    let mut resources = None;
+    while start.elapsed() < max_wait {
+        let state = match driver
+            .execute_script(
+                "return [document.readyState, performance.getEntriesByType('resource').length];",
+            )
+            .await
+        {
+            Ok(x) => x.convert::<(String, usize)>(),
+            Err(_) => return,
+        };
+        match state {
+            Ok((ready, x)) if ready == "complete" && resources == Some(x) => return,
+            Ok((_, x)) => resources = Some(x),
+            Err(_) => return,
+        }
+        time::sleep(NETWORK_IDLE_POLL).await;
+    }
+}
+
+/// Function to check if a session still answers after a failed page.
+async fn is_alive(driver: &WebDriver) -> bool {
+    matches!(
+        time::timeout(ALIVE_TIMEOUT, driver.title()).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Function to close a session.
+/// Dropping a session blocks on deleting it, so it is dropped on a blocking thread.
+/// This also works for sessions which are broken, where quitting would fail.
+async fn close_session(driver: WebDriver) {
+    let _ = tokio::task::spawn_blocking(move || drop(driver)).await;
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// A 1x1 png, base64 encoded like WebDriver screenshots are.
+    pub const PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
+
+    /// Rendered DOM of every page of the WebDriver stand-in.
+    pub const DOM: &str = r#"<html><body><a href="/rendered">Mock page</a></body></html>"#;
+
+    /// State of the WebDriver stand-in.
+    /// Requests are logged as `METHOD path body`.
+    #[derive(Debug, Default)]
+    pub struct MockState {
+        pub requests: Vec<String>,
+        pub sessions: usize,
+        pub refused_after_crash: usize,
+        crashed: HashSet<String>,
+    }
+
+    pub type Mock = Arc<std::sync::Mutex<MockState>>;
+
+    /// Local WebDriver stand-in.
+    /// Starts sessions, accepts every command and answers screenshots with a 1x1 png.
+    /// Every page has the title `Mock page`, a 1280x720 viewport and is 5000 pixels high.
+    /// Full page screenshots are supported for both Chrome and Firefox.
+    /// Loading a url containing `crash` kills the session, and one containing `hang` takes 3 seconds.
+    /// After a crash, as many new sessions as `refused_after_crash` are refused.
+    /// Returns the url of the server and its state.
+    pub async fn spawn_webdriver() -> (String, Mock) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mock = Mock::default();
+        let mock_clone = mock.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, mock_clone.clone()));
+            }
+        });
+        (format!("http://{}", addr), mock)
+    }
+
+    /// Function to answer the requests of one keep-alive connection.
+    async fn serve_connection(stream: TcpStream, mock: Mock) {
+        let mut stream = BufReader::new(stream);
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let mut parts = line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_string();
+            let path = parts.next().unwrap_or_default().to_string();
+
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                if stream.read_line(&mut header).await.unwrap_or(0) == 0 {
+                    return;
+                }
+                let header = header.trim();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap_or(0);
+                    }
+                }
+            }
+            let mut body = vec![0u8; length];
+            if stream.read_exact(&mut body).await.is_err() {
+                return;
+            }
+            let body = String::from_utf8_lossy(&body).to_string();
+
+            if path.ends_with("/url") && body.contains("hang") {
+                time::sleep(Duration::from_secs(3)).await;
+            }
+            let (status, value) = answer(&mock, &method, &path, &body);
+            let response = serde_json::json!({ "value": value }).to_string();
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                response.len(),
+                response
+            );
+            if stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Function to get the status and value answering a WebDriver command.
+    fn answer(
+        mock: &Mock,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> (&'static str, serde_json::Value) {
+        let mut mock = mock.lock().unwrap();
+        mock.requests.push(format!("{} {} {}", method, path, body));
+        if method == "POST" && path == "/session" {
+            if !mock.crashed.is_empty() && mock.refused_after_crash > 0 {
+                mock.refused_after_crash -= 1;
+                let error = serde_json::json!({"error": "session not created", "message": "busy"});
+                return ("500 Internal Server Error", error);
+            }
+            mock.sessions += 1;
+            let id = format!("mock-{}", mock.sessions);
+            return (
+                "200 OK",
+                serde_json::json!({"sessionId": id, "capabilities": {}}),
+            );
+        }
+
+        let session = path.split('/').nth(2).unwrap_or_default().to_string();
+        if path.ends_with("/url") && body.contains("crash") {
+            mock.crashed.insert(session.clone());
+        }
+        if mock.crashed.contains(&session) && method != "DELETE" {
+            let error = serde_json::json!({"error": "invalid session id", "message": "crashed"});
+            return ("404 Not Found", error);
+        }
+        if path.ends_with("/screenshot") {
+            ("200 OK", serde_json::json!(PNG))
+        } else if path.ends_with("/title") {
+            ("200 OK", serde_json::json!("Mock page"))
+        } else if path.ends_with("/execute/sync") && body.contains("innerWidth") {
+            ("200 OK", serde_json::json!([1280, 720]))
+        } else if path.ends_with("/execute/sync") && body.contains("readyState") {
+            ("200 OK", serde_json::json!(["complete", 3]))
+        } else if path.ends_with("/execute/sync") && body.contains("scrollHeight") {
+            ("200 OK", serde_json::json!([1280, 5000]))
+        } else if path.ends_with("/window/rect") && method == "GET" {
+            let rect = serde_json::json!({"x": 0, "y": 0, "width": 1280, "height": 720});
+            ("200 OK", rect)
+        } else if path.ends_with("/goog/cdp/execute") && body.contains("getLayoutMetrics") {
+            let size = serde_json::json!({"cssContentSize": {"width": 1280, "height": 5000}});
+            ("200 OK", size)
+        } else if path.ends_with("/goog/cdp/execute") && body.contains("captureScreenshot") {
+            ("200 OK", serde_json::json!({ "data": PNG }))
+        } else if path.ends_with("/moz/screenshot/full") {
+            ("200 OK", serde_json::json!(PNG))
+        } else if path.ends_with("/source") {
+            ("200 OK", serde_json::json!(DOM))
+        } else {
+            ("200 OK", serde_json::Value::Null)
+        }
+    }
+
+    pub fn default_options(url: String) -> SeleniumOptions {
+        SeleniumOptions {
+            webdriver_url: url,
+            browser: Browser::Chrome,
+            headless: false,
+            window_size: None,
+            browser_args: Vec::new(),
+            page_load_timeout: 5,
+            sessions: 1,
+            screenshot_timeout: 5,
+            full_page: false,
+            save_dom: false,
+            policy: NetworkPolicy::default(),
+        }
+    }
+
+    /// Function to take the screenshots of urls with the stand-in.
+    /// Returns the names of the files in the screenshots folder, sorted, and the index.
+    async fn take_screenshots(
+        options: SeleniumOptions,
+        links: &[&str],
+        name: &str,
+    ) -> (Vec<String>, serde_json::Value) {
+        let folder = std::env::temp_dir().join(format!("crate-{}-{}", name, std::process::id()));
+        let (tx, rx) = mpsc::channel(links.len());
+        for x in links {
+            tx.send(Link::new_from_str(x).unwrap()).await.unwrap();
+        }
+        drop(tx);
+        handle_selenium(Some(folder.clone()), Some(options), rx)
+            .await
+            .unwrap();
+
+        let screenshots = folder.join("screenshots");
+        let mut files: Vec<String> = std::fs::read_dir(&screenshots)
+            .unwrap()
+            .map(|x| x.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        for x in files.iter().filter(|x| x.ends_with(".png")) {
+            let png = std::fs::read(screenshots.join(x)).unwrap();
+            assert!(png.starts_with(b"\x89PNG"));
+        }
+        let index = std::fs::read(screenshots.join("index.json")).unwrap();
+        let _ = std::fs::remove_dir_all(&folder);
+        (files, serde_json::from_slice(&index).unwrap())
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!("Firefox".parse::<Browser>(), Ok(Browser::Firefox));
+        assert!("opera".parse::<Browser>().is_err());
+        assert_eq!(
+            "1280x720".parse::<WindowSize>(),
+            Ok(WindowSize {
+                width: 1280,
+                height: 720
+            })
+        );
+        assert!("1280".parse::<WindowSize>().is_err());
+        assert!("0x720".parse::<WindowSize>().is_err());
+    }
+
+    #[test]
+    fn screenshot_names() {
+        let name = |x: &str| screenshot_name(&x.parse().unwrap());
+
+        assert!(name("https://example.com/").starts_with("example.com-"));
+        assert!(name("https://example.com/a/b?c=d#e").starts_with("example.com_a_b_c_d-"));
+        assert_ne!(
+            name("https://example.com/a?b"),
+            name("https://example.com/a_b")
+        );
+        assert_eq!(name("https://example.com/x"), name("https://example.com/x"));
+        assert!(name(&format!("https://example.com/{}", "a".repeat(500))).len() < 130);
+    }
+
+    #[tokio::test]
+    async fn session_options() {
+        let (url, mock) = spawn_webdriver().await;
+        let options = SeleniumOptions {
+            browser: Browser::Firefox,
+            headless: true,
+            window_size: Some(WindowSize {
+                width: 800,
+                height: 600,
+            }),
+            browser_args: vec!["-private".to_string()],
+            ..default_options(url)
+        };
+        let links = ["http://example.com/", "http://example.com/a"];
+        let (files, index) = take_screenshots(options, &links, "options").await;
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[2], "index.json");
+        assert_eq!(index[0]["file"], files[0].as_str());
+        assert_eq!(index[0]["url"], "http://example.com/");
+        assert_eq!(index[0]["title"], "Mock page");
+        assert_eq!(index[0]["viewport"]["width"], 1280);
+
+        let requests = &mock.lock().unwrap().requests;
+        assert!(requests[0].starts_with("POST /session "));
+        assert!(requests[0].contains(r#""args":["--headless","-private"]"#));
+        assert!(requests
+            .iter()
+            .any(|x| x.contains(r#"/timeouts {"pageLoad":5000}"#)));
+        assert!(requests
+            .iter()
+            .any(|x| x.contains(r#""width":800,"height":600"#)));
+        assert!(!requests.iter().any(|x| x.contains("/window/fullscreen")));
+        assert!(requests
+            .last()
+            .unwrap()
+            .starts_with("DELETE /session/mock-1"));
+    }
+
+    #[tokio::test]
+    async fn full_page_capture() {
+        let (url, mock) = spawn_webdriver().await;
+        let options = SeleniumOptions {
+            full_page: true,
+            save_dom: true,
+            ..default_options(url.clone())
+        };
+        let (files, index) =
+            take_screenshots(options, &["http://cdn.png.example.com/"], "chrome").await;
+        assert_eq!(files.len(), 3);
+        assert_eq!(index[0]["dom"], files[0].as_str());
+        assert!(files[0].starts_with("cdn.png.example.com-"));
+        assert!(files[0].ends_with(".html"));
+        {
+            let requests = &mock.lock().unwrap().requests;
+            assert!(requests
+                .iter()
+                .any(|x| x.contains("Page.captureScreenshot")));
+            assert!(requests.iter().any(|x| x.contains(r#""height":5000"#)));
+            assert!(!requests.iter().any(|x| x.ends_with("/screenshot ")));
+        }
+
+        let options = SeleniumOptions {
+            browser: Browser::Firefox,
+            full_page: true,
+            ..default_options(url)
+        };
+        let (files, _) = take_screenshots(options, &["http://example.com/"], "firefox").await;
+        assert_eq!(files.len(), 2);
+        let requests = &mock.lock().unwrap().requests;
+        assert!(requests.iter().any(|x| x.contains("/moz/screenshot/full")));
+    }
+
+    #[tokio::test]
+    async fn render_pages() {
+        let (url, mock) = spawn_webdriver().await;
+        let renderer = Renderer::new(default_options(url), 5);
+
+        assert_eq!(renderer.render("http://example.com/").await.unwrap(), DOM);
+        assert_eq!(renderer.render("http://example.com/a").await.unwrap(), DOM);
+        assert!(renderer.render("http://example.com/crash").await.is_err());
+        assert_eq!(renderer.render("http://example.com/b").await.unwrap(), DOM);
+        renderer.close().await;
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.sessions, 2);
+        assert!(mock.requests.iter().any(|x| x.contains("readyState")));
+        let deleted = mock.requests.iter().filter(|x| x.starts_with("DELETE"));
+        assert_eq!(deleted.count(), 2);
+    }
+
+    #[tokio::test]
+    async fn session_pool() {
+        let (url, mock) = spawn_webdriver().await;
+        let options = SeleniumOptions {
+            sessions: 2,
+            screenshot_timeout: 1,
+            ..default_options(url)
+        };
+        let links = [
+            "http://example.com/a",
+            "http://example.com/crash",
+            "http://example.com/b",
+            "http://example.com/hang",
+            "http://example.com/c",
+        ];
+        let (files, index) = take_screenshots(options, &links, "pool").await;
+        assert_eq!(files.len(), 4);
+        assert!(!files.iter().any(|x| x.contains("hang")));
+        assert_eq!(index.as_array().unwrap().len(), 3);
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.sessions, 3);
+        let deleted = mock.requests.iter().filter(|x| x.starts_with("DELETE"));
+        assert_eq!(deleted.count(), 3);
+    }
+
+    #[tokio::test]
+    async fn refused_session() {
+        let (url, mock) = spawn_webdriver().await;
+        mock.lock().unwrap().refused_after_crash = 1;
+        let links = ["http://example.com/crash", "http://example.com/a"];
+        let (files, index) = take_screenshots(default_options(url), &links, "refused").await;
+        assert_eq!(files.len(), 2);
+        assert_eq!(index[0]["url"], "http://example.com/a");
+        assert_eq!(mock.lock().unwrap().sessions, 2);
+    }
+
+    #[tokio::test]
+    async fn refused_host() {
+        let (url, mock) = spawn_webdriver().await;
+        let options = SeleniumOptions {
+            policy: NetworkPolicy::new(true, &[]),
+            ..default_options(url)
+        };
+        let links = ["http://127.0.0.1/", "http://example.com/a"];
+        let (files, index) = take_screenshots(options, &links, "policy").await;
+        assert_eq!(files.len(), 2);
+        assert_eq!(index.as_array().unwrap().len(), 1);
+        assert_eq!(index[0]["url"], "http://example.com/a");
+        let mock = mock.lock().unwrap();
+        assert!(!mock.requests.iter().any(|x| x.contains("127.0.0.1/")));
+    }
+}
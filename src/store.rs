@@ -0,0 +1,219 @@
+/*!
+Module containing the storage of the urls of a crawl, in memory or on disk.
+*/
+use crate::extractors::content::fnv1a;
+use reqwest::Url;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Share of the urls not in a set which the Bloom filter wrongly lets through to the disk.
+const BLOOM_FALSE_POSITIVES: f64 = 0.01;
+
+/// Where the crawler keeps its urls.
+#[derive(Clone, Debug, Default)]
+pub struct Store {
+    db: Option<sled::Db>,
+    bloom_size: Option<usize>,
+}
+
+impl Store {
+    /// Creates a new Store keeping the urls in memory.
+    pub fn memory() -> Self {
+        Store::default()
+    }
+
+    /// Creates a new Store keeping the urls in a database at the path.
+    /// The sets on disk get a Bloom filter for the expected number of urls if it is given.
+    pub fn open(path: &Path, bloom_size: Option<usize>) -> Result<Self, String> {
+        let db = match sled::open(path) {
+            Ok(x) => x,
+            Err(e) => return Err(format!("Could not open the frontier database: {}", e)),
+        };
+        for name in db.tree_names() {
+            if name != db.name() {
+                db.drop_tree(name).map_err(db_error)?;
+            }
+        }
+        Ok(Store {
+            db: Some(db),
+            bloom_size,
+        })
+    }
+
+    /// Function to get the emptied tree with the name, if the urls are kept on disk.
+    pub fn tree(&self, name: &str) -> Result<Option<sled::Tree>, String> {
+        match &self.db {
+            Some(x) => {
+                let tree = x.open_tree(name).map_err(db_error)?;
+                tree.clear().map_err(db_error)?;
+                Ok(Some(tree))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Function to get an empty Bloom filter for the expected number of urls, if one is set.
+    pub fn bloom(&self) -> Option<Bloom> {
+        self.bloom_size.map(Bloom::new)
+    }
+
+    /// Function to get an empty set of urls with the name.
+    pub fn url_set(&self, name: &str) -> Result<UrlSet, String> {
+        match self.tree(name)? {
+            Some(tree) => Ok(UrlSet::Disk {
+                tree,
+                bloom: self.bloom(),
+            }),
+            None => Ok(UrlSet::Memory(HashSet::new())),
+        }
+    }
+}
+
+/// Function to turn an error of the database into the error of the crawler.
+pub fn db_error(e: sled::Error) -> String {
+    format!("Frontier database error: {}", e)
+}
+
+/// Set of urls, in memory or in a tree of the database.
+#[derive(Debug)]
+pub enum UrlSet {
+    Memory(HashSet<Url>),
+    Disk {
+        tree: sled::Tree,
+        bloom: Option<Bloom>,
+    },
+}
+
+impl UrlSet {
+    /// Function to check if a url is in the set.
+    pub fn contains(&self, url: &Url) -> Result<bool, String> {
+        match self {
+            UrlSet::Memory(x) => Ok(x.contains(url)),
+            UrlSet::Disk { tree, bloom } => {
+                if bloom.as_ref().is_some_and(|x| !x.contains(url.as_str())) {
+                    return Ok(false);
+                }
+                tree.contains_key(url.as_str()).map_err(db_error)
+            }
+        }
+    }
+
+    /// Function to add a url to the set.
+    /// Returns false if it was already in the set.
+    pub fn insert(&mut self, url: Url) -> Result<bool, String> {
+        match self {
+            UrlSet::Memory(x) => Ok(x.insert(url)),
+            UrlSet::Disk { tree, bloom } => {
+                if let Some(x) = bloom {
+                    x.insert(url.as_str());
+                }
+                let old = tree.insert(url.as_str(), &[]).map_err(db_error)?;
+                Ok(old.is_none())
+            }
+        }
+    }
+
+    /// Function to get all the urls of the set.
+    pub fn urls(&self) -> Result<Vec<Url>, String> {
+        match self {
+            UrlSet::Memory(x) => Ok(x.iter().cloned().collect()),
+            UrlSet::Disk { tree, .. } => {
+                let mut urls = Vec::new();
+                for x in tree.iter().keys() {
+                    let key = x.map_err(db_error)?;
+                    if let Ok(x) = Url::parse(&String::from_utf8_lossy(&key)) {
+                        urls.push(x);
+                    }
+                }
+                Ok(urls)
+            }
+        }
+    }
+}
+
+/// Bloom filter over strings.
+/// Never misses a string which was inserted, but finds some which were not.
+#[derive(Clone, Debug)]
+pub struct Bloom {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl Bloom {
+    /// Creates a new Bloom filter for the expected number of strings.
+    pub fn new(expected: usize) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(expected.max(1) as f64) * BLOOM_FALSE_POSITIVES.ln() / (ln2 * ln2)).ceil();
+        let words = (bits as usize).div_ceil(64).max(1);
+        let hashes = (words as f64 * 64.0 / expected.max(1) as f64 * ln2).round();
+        Bloom {
+            bits: vec![0; words],
+            hashes: (hashes as u32).clamp(1, 16),
+        }
+    }
+
+    /// Function to get the bits of a string, by double hashing.
+    fn positions(&self, value: &str) -> impl Iterator<Item = usize> {
+        let h1 = fnv1a(value.as_bytes());
+        let h2 = fnv1a(&h1.to_le_bytes()) | 1;
+        let size = self.bits.len() as u64 * 64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % size) as usize)
+    }
+
+    /// Function to add a string to the filter.
+    pub fn insert(&mut self, value: &str) {
+        for x in self.positions(value).collect::<Vec<_>>() {
+            self.bits[x / 64] |= 1 << (x % 64);
+        }
+    }
+
+    /// Function to check if a string may have been added.
+    pub fn contains(&self, value: &str) -> bool {
+        self.positions(value)
+            .all(|x| self.bits[x / 64] & (1 << (x % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bloom_filter() {
+        let mut bloom = Bloom::new(1000);
+        for x in 0..1000 {
+            bloom.insert(&format!("https://example.com/{}", x));
+        }
+        assert!((0..1000).all(|x| bloom.contains(&format!("https://example.com/{}", x))));
+        let found = (1000..11000)
+            .filter(|x| bloom.contains(&format!("https://example.com/{}", x)))
+            .count();
+        assert!(found < 300, "{} false positives", found);
+    }
+
+    #[test]
+    fn url_sets() {
+        let path = std::env::temp_dir().join(format!("crate-store-{}", std::process::id()));
+        let url = |x: &str| Url::parse(&format!("https://example.com/{}", x)).unwrap();
+        {
+            let store = Store::open(&path, Some(100)).unwrap();
+            let mut set = store.url_set("seen").unwrap();
+            assert!(set.insert(url("a")).unwrap());
+            assert!(!set.insert(url("a")).unwrap());
+            assert!(set.contains(&url("a")).unwrap());
+            assert!(!set.contains(&url("b")).unwrap());
+            assert_eq!(set.urls().unwrap(), vec![url("a")]);
+        }
+        let store = Store::open(&path, None).unwrap();
+        let set = store.url_set("seen").unwrap();
+        let cleared = set.urls().unwrap().is_empty();
+        drop(set);
+        drop(store);
+        let _ = std::fs::remove_dir_all(&path);
+        assert!(cleared);
+
+        let mut set = Store::memory().url_set("seen").unwrap();
+        assert!(set.insert(url("a")).unwrap());
+        assert!(set.contains(&url("a")).unwrap());
+    }
+}
@@ -0,0 +1,300 @@
+/*!
+Module containing the detection of crawler traps.
+*/
+use crate::extractors::content::fnv1a;
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Number of urls kept as examples of every cut.
+const MAX_EXAMPLES: usize = 5;
+
+/// Limits of the trap detection. No traps are detected by default.
+#[derive(Clone, Debug, Default)]
+pub struct TrapLimits {
+    pub max_url_length: Option<usize>,
+    pub max_path_depth: Option<usize>,
+    pub max_segment_repeats: Option<usize>,
+    pub max_query_variants: Option<usize>,
+    pub max_similar_pages: Option<usize>,
+}
+
+/// Kind of trap which cut an url.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trap {
+    UrlLength,
+    PathDepth,
+    RepeatingSegments,
+    QueryVariants,
+    SimilarContent,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trap::UrlLength => write!(f, "url length"),
+            Trap::PathDepth => write!(f, "path depth"),
+            Trap::RepeatingSegments => write!(f, "repeating segments"),
+            Trap::QueryVariants => write!(f, "query variants"),
+            Trap::SimilarContent => write!(f, "similar content"),
+        }
+    }
+}
+
+/// Urls cut by a trap for a pattern, written at the end of the crawl.
+#[derive(Clone, Debug, Serialize)]
+pub struct TrapCut {
+    pub pattern: String,
+    pub trap: Trap,
+    pub urls: usize,
+    pub examples: Vec<String>,
+}
+
+/// Urls and pages seen so far.
+#[derive(Debug, Default)]
+struct TrapState {
+    queries: HashMap<String, HashSet<u64>>,
+    contents: HashMap<(String, u64), usize>,
+    similar_patterns: HashSet<String>,
+    cuts: BTreeMap<(String, Trap), TrapCut>,
+}
+
+impl TrapState {
+    /// Function to record an url cut by a trap, logging the first cut of a pattern.
+    fn cut(&mut self, pattern: String, trap: Trap, url: &Url) {
+        let cut = self.cuts.entry((pattern.clone(), trap)).or_insert_with(|| {
+            println!("Cutting crawler trap {} ({})", pattern, trap);
+            TrapCut {
+                pattern,
+                trap,
+                urls: 0,
+                examples: Vec::new(),
+            }
+        });
+        cut.urls += 1;
+        if cut.examples.len() < MAX_EXAMPLES {
+            cut.examples.push(url.to_string());
+        }
+    }
+}
+
+/// Detector of crawler traps.
+#[derive(Clone, Debug, Default)]
+pub struct TrapDetector {
+    limits: TrapLimits,
+    state: Arc<Mutex<TrapState>>,
+}
+
+impl TrapDetector {
+    /// Creates a new TrapDetector.
+    pub fn new(limits: TrapLimits) -> Self {
+        TrapDetector {
+            limits,
+            state: Arc::default(),
+        }
+    }
+
+    /// Function to check if an url should be cut before it is added to the frontier.
+    /// Returns the trap which cut it.
+    pub fn check_url(&self, url: &Url) -> Option<Trap> {
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|x| x.filter(|x| !x.is_empty()).collect())
+            .unwrap_or_default();
+        let trap = if self
+            .limits
+            .max_url_length
+            .is_some_and(|x| url.as_str().len() > x)
+        {
+            Some(Trap::UrlLength)
+        } else if self
+            .limits
+            .max_path_depth
+            .is_some_and(|x| segments.len() > x)
+        {
+            Some(Trap::PathDepth)
+        } else if self
+            .limits
+            .max_segment_repeats
+            .is_some_and(|x| most_repeats(&segments) > x)
+        {
+            Some(Trap::RepeatingSegments)
+        } else {
+            None
+        };
+
+        if trap.is_some() {
+            return trap;
+        }
+        let mut state = self.state.lock().ok()?;
+        if state.similar_patterns.contains(&url_pattern(url)) {
+            return Some(Trap::SimilarContent);
+        }
+        if let (Some(max), Some(query)) = (self.limits.max_query_variants, url.query()) {
+            let queries = state.queries.entry(path_key(url)).or_default();
+            let hash = fnv1a(query.as_bytes());
+            if !queries.contains(&hash) {
+                if queries.len() >= max {
+                    return Some(Trap::QueryVariants);
+                }
+                queries.insert(hash);
+            }
+        }
+        None
+    }
+
+    /// Function to record an url cut by a trap of check_url.
+    /// Called once for every url, so urls linked many times are counted once.
+    pub fn record(&self, url: &Url, trap: Trap) {
+        let pattern = match trap {
+            Trap::SimilarContent => url_pattern(url),
+            Trap::QueryVariants => path_key(url),
+            _ => url.host_str().unwrap_or_default().to_string(),
+        };
+        if let Ok(mut x) = self.state.lock() {
+            x.cut(pattern, trap, url);
+        }
+    }
+
+    /// Function to check if the links of a page should be cut, going by its content.
+    /// Content is compared without digits and whitespace, so pages differing only
+    /// by dates or counters are the same.
+    pub fn check_content(&self, url: &Url, body: &[u8]) -> Option<Trap> {
+        let max = self.limits.max_similar_pages?;
+        let normalized: Vec<u8> = body
+            .iter()
+            .copied()
+            .filter(|x| !x.is_ascii_digit() && !x.is_ascii_whitespace())
+            .collect();
+        let pattern = url_pattern(url);
+        let mut state = self.state.lock().ok()?;
+        let count = state
+            .contents
+            .entry((pattern.clone(), fnv1a(&normalized)))
+            .or_insert(0);
+        *count += 1;
+        if *count > max {
+            state.similar_patterns.insert(pattern.clone());
+            state.cut(pattern, Trap::SimilarContent, url);
+            return Some(Trap::SimilarContent);
+        }
+        None
+    }
+
+    /// Function to get the cuts so far, sorted by pattern.
+    pub fn cuts(&self) -> Vec<TrapCut> {
+        match self.state.lock() {
+            Ok(x) => x.cuts.values().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Function to get the number of times the most repeated segment of a path appears.
+fn most_repeats(segments: &[&str]) -> usize {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for x in segments {
+        *counts.entry(x).or_insert(0) += 1;
+    }
+    counts.values().copied().max().unwrap_or(0)
+}
+
+/// Function to get the host and path of an url.
+fn path_key(url: &Url) -> String {
+    format!("{}{}", url.host_str().unwrap_or_default(), url.path())
+}
+
+/// Function to get the pattern of an url, with the path segments containing digits as `*`.
+fn url_pattern(url: &Url) -> String {
+    let path: Vec<&str> = url
+        .path()
+        .split('/')
+        .map(|x| {
+            if x.bytes().any(|x| x.is_ascii_digit()) {
+                "*"
+            } else {
+                x
+            }
+        })
+        .collect();
+    format!("{}{}", url.host_str().unwrap_or_default(), path.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(x: &str) -> Url {
+        Url::parse(&format!("https://example.com/{}", x)).unwrap()
+    }
+
+    /// Function to check an url and record it if it is cut, like the crawler does.
+    fn check(traps: &TrapDetector, x: &str) -> Option<Trap> {
+        let trap = traps.check_url(&url(x));
+        if let Some(y) = trap {
+            traps.record(&url(x), y);
+        }
+        trap
+    }
+
+    #[test]
+    fn trap_urls() {
+        let traps = TrapDetector::new(TrapLimits {
+            max_url_length: Some(60),
+            max_path_depth: Some(4),
+            max_segment_repeats: Some(2),
+            max_query_variants: Some(2),
+            max_similar_pages: None,
+        });
+        assert_eq!(check(&traps, "a/b/c/d"), None);
+        assert_eq!(check(&traps, "a/b/c/d/e"), Some(Trap::PathDepth));
+        assert_eq!(check(&traps, &"x".repeat(60)), Some(Trap::UrlLength));
+        assert_eq!(check(&traps, "a/b/a/a"), Some(Trap::RepeatingSegments));
+        assert_eq!(check(&traps, "search?q=1"), None);
+        assert_eq!(check(&traps, "search?q=2"), None);
+        assert_eq!(check(&traps, "search?q=1"), None);
+        assert_eq!(check(&traps, "search?q=3"), Some(Trap::QueryVariants));
+        assert_eq!(check(&traps, "other?q=3"), None);
+
+        let cuts = traps.cuts();
+        assert_eq!(cuts.len(), 4);
+        assert!(cuts
+            .iter()
+            .any(|x| x.pattern == "example.com/search" && x.trap == Trap::QueryVariants));
+        assert!(TrapDetector::default()
+            .check_url(&url(&"a/".repeat(100)))
+            .is_none());
+    }
+
+    #[test]
+    fn similar_content() {
+        let traps = TrapDetector::new(TrapLimits {
+            max_similar_pages: Some(2),
+            ..TrapLimits::default()
+        });
+        let page = |x: &str| format!("<html><h1>Events on {}</h1>None</html>", x);
+        assert_eq!(
+            traps.check_content(&url("cal/2024-01-01"), page("2024-01-01").as_bytes()),
+            None
+        );
+        assert_eq!(
+            traps.check_content(&url("cal/2024-01-02"), page("2024-01-02").as_bytes()),
+            None
+        );
+        assert_eq!(
+            traps.check_content(&url("cal/about"), page("2024-01-03").as_bytes()),
+            None
+        );
+        assert_eq!(
+            traps.check_content(&url("cal/2024-01-03"), page("2024-01-03").as_bytes()),
+            Some(Trap::SimilarContent)
+        );
+        assert_eq!(check(&traps, "cal/2024-01-04"), Some(Trap::SimilarContent));
+        assert_eq!(check(&traps, "cal/about"), None);
+        assert!(traps.check_url(&url("cal/2024-01-04")).is_some());
+        assert_eq!(traps.cuts()[0].urls, 2);
+    }
+}
@@ -0,0 +1,200 @@
+/*!
+Module containing the triggers deciding which pages get a screenshot.
+*/
+use crate::extractors::links::Link;
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+/// Condition for taking the screenshot of a page.
+#[derive(Clone, Debug)]
+pub enum Trigger {
+    /// Pages flagged by the search words or the rules.
+    Flagged,
+    /// Pages at most this deep from the seed url.
+    Depth(usize),
+    /// Pages with an url matching the regex.
+    Url(Regex),
+    /// Pages with a status code in the range.
+    Status(u16, u16),
+    /// Pages whose content changed since the last crawl.
+    Changed,
+}
+
+impl FromStr for Trigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+        let invalid = || format!("Invalid trigger {}", s);
+        match (name, value) {
+            ("flagged", None) => Ok(Trigger::Flagged),
+            ("changed", None) => Ok(Trigger::Changed),
+            ("depth", Some(x)) => x.parse().map(Trigger::Depth).map_err(|_| invalid()),
+            ("url", Some(x)) => Regex::new(x).map(Trigger::Url).map_err(|_| invalid()),
+            ("status", None) => Ok(Trigger::Status(400, 599)),
+            ("status", Some(x)) => parse_status(x).ok_or_else(invalid),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Function to parse a status code like `404` or a class of them like `4xx`.
+fn parse_status(status: &str) -> Option<Trigger> {
+    match status.strip_suffix("xx") {
+        Some(x) if x.len() == 1 => {
+            let class = x.parse::<u16>().ok()?;
+            Some(Trigger::Status(class * 100, class * 100 + 99))
+        }
+        Some(_) => None,
+        None => {
+            let code = status.parse().ok()?;
+            Some(Trigger::Status(code, code))
+        }
+    }
+}
+
+impl Trigger {
+    /// Function to check if the trigger fires for a page.
+    fn fires(&self, link: &Link, depth: usize, flagged: bool, changed: bool) -> bool {
+        match self {
+            Trigger::Flagged => flagged,
+            Trigger::Depth(x) => depth <= *x,
+            Trigger::Url(x) => x.is_match(link.url.as_str()),
+            Trigger::Status(min, max) => link.status.is_some_and(|x| *min <= x && x <= *max),
+            Trigger::Changed => changed,
+        }
+    }
+}
+
+/// List of triggers for the screenshots.
+/// Flagged pages get a screenshot if no triggers are given.
+#[derive(Clone, Debug)]
+pub struct Triggers {
+    triggers: Vec<Trigger>,
+}
+
+impl Triggers {
+    /// Creates a new list of triggers.
+    pub fn new(triggers: Vec<Trigger>) -> Self {
+        if triggers.is_empty() {
+            Triggers {
+                triggers: vec![Trigger::Flagged],
+            }
+        } else {
+            Triggers { triggers }
+        }
+    }
+
+    /// Function to check if a page should get a screenshot.
+    pub fn fires(&self, link: &Link, depth: usize, flagged: bool, changed: bool) -> bool {
+        self.triggers
+            .iter()
+            .any(|x| x.fires(link, depth, flagged, changed))
+    }
+}
+
+impl Default for Triggers {
+    fn default() -> Self {
+        Triggers::new(Vec::new())
+    }
+}
+
+/// Hashes of the content of the pages, in this crawl and in the last one.
+/// Used to find the pages whose content changed.
+#[derive(Clone, Debug, Default)]
+pub struct ContentHashes {
+    previous: Arc<HashMap<String, String>>,
+    current: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl ContentHashes {
+    /// Creates new ContentHashes from the hashes of the last crawl.
+    pub fn new(previous: HashMap<String, String>) -> Self {
+        ContentHashes {
+            previous: Arc::new(previous),
+            current: Arc::default(),
+        }
+    }
+
+    /// Function to record the hash of the content of a page.
+    /// Returns true if the page was crawled last time with a different content.
+    pub fn update(&self, url: &str, hash: u64) -> bool {
+        let hash = format!("{:016x}", hash);
+        let changed = self.previous.get(url).is_some_and(|x| *x != hash);
+        if let Ok(mut x) = self.current.lock() {
+            x.insert(url.to_string(), hash);
+        }
+        changed
+    }
+
+    /// Function to keep the hash of a page from the last crawl, when its content was not downloaded.
+    pub fn keep(&self, url: &str) {
+        if let (Some(hash), Ok(mut x)) = (self.previous.get(url), self.current.lock()) {
+            x.insert(url.to_string(), hash.clone());
+        }
+    }
+
+    /// Function to get the hashes of this crawl, sorted by url.
+    pub fn hashes(&self) -> BTreeMap<String, String> {
+        match self.current.lock() {
+            Ok(x) => x.clone(),
+            Err(_) => BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshot_triggers() {
+        let triggers = Triggers::new(vec![
+            "depth:1".parse().unwrap(),
+            "url:/admin/".parse().unwrap(),
+            "status:5xx".parse().unwrap(),
+            "changed".parse().unwrap(),
+        ]);
+        let mut link = Link::new_from_str("https://example.com/page").unwrap();
+
+        assert!(triggers.fires(&link, 1, false, false));
+        assert!(!triggers.fires(&link, 2, true, false));
+        assert!(triggers.fires(&link, 2, false, true));
+        link.status = Some(503);
+        assert!(triggers.fires(&link, 2, false, false));
+        link.status = Some(404);
+        assert!(!triggers.fires(&link, 2, false, false));
+        let link = Link::new_from_str("https://example.com/admin/users").unwrap();
+        assert!(triggers.fires(&link, 2, false, false));
+
+        assert!(Triggers::default().fires(&link, 5, true, false));
+        assert!(matches!("status".parse(), Ok(Trigger::Status(400, 599))));
+        assert!(matches!(
+            "status:404".parse(),
+            Ok(Trigger::Status(404, 404))
+        ));
+        assert!("status:40x".parse::<Trigger>().is_err());
+        assert!("depth".parse::<Trigger>().is_err());
+        assert!("url:(".parse::<Trigger>().is_err());
+    }
+
+    #[test]
+    fn changed_content() {
+        let mut previous = HashMap::new();
+        previous.insert("https://example.com/".to_string(), format!("{:016x}", 1));
+        previous.insert("https://example.com/a".to_string(), format!("{:016x}", 2));
+        let hashes = ContentHashes::new(previous);
+
+        assert!(!hashes.update("https://example.com/", 1));
+        assert!(hashes.update("https://example.com/a", 3));
+        assert!(!hashes.update("https://example.com/new", 4));
+        assert_eq!(hashes.hashes().len(), 3);
+    }
+}
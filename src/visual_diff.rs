@@ -0,0 +1,351 @@
+/*!
+Module containing the visual diff of the screenshots of two crawls.
+*/
+use crate::file_handler;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::fs;
+
+/// Width and height of the blocks compared.
+const BLOCK_SIZE: usize = 8;
+/// Structural similarity below which a block counts as changed.
+const MIN_SIMILARITY: f64 = 0.9;
+/// Constants stabilizing the structural similarity of dark and flat blocks.
+const C1: f64 = 0.01 * 0.01;
+const C2: f64 = 0.03 * 0.03;
+
+/// Page whose screenshot changed more than the threshold.
+/// Files are relative to their screenshots folder.
+#[derive(Clone, Debug, Serialize)]
+pub struct VisualChange {
+    pub url: String,
+    pub score: f64,
+    pub previous: String,
+    pub current: String,
+    pub diff: String,
+}
+
+/// Report of the visual diff, with the changed pages sorted by score.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VisualDiff {
+    pub changed: Vec<VisualChange>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unreadable: Vec<String>,
+}
+
+/// Function to compare the screenshots of an earlier crawl with the current ones.
+/// Writes the diff images and `visual_diff.json` into the current folder.
+pub async fn compare_screenshots(
+    previous: PathBuf,
+    current: PathBuf,
+    threshold: f64,
+) -> Result<VisualDiff, String> {
+    let previous_files = match file_handler::read_screenshot_index(previous.clone()).await {
+        Ok(x) => screenshot_files(x),
+        Err(_) => return Err("Error in reading the previous Screenshot Index".to_string()),
+    };
+    let current_files = match file_handler::read_screenshot_index(current.clone()).await {
+        Ok(x) => screenshot_files(x),
+        Err(_) => return Err("Error in reading the Screenshot Index".to_string()),
+    };
+    if fs::create_dir_all(current.join("diff")).await.is_err() {
+        return Err("Could not create the diff folder".to_string());
+    }
+
+    let mut report = VisualDiff::default();
+    for (url, file) in &current_files {
+        let previous_file = match previous_files.get(url) {
+            Some(x) => x,
+            None => {
+                report.added.push(url.clone());
+                continue;
+            }
+        };
+        let (a, b) = match (
+            fs::read(previous.join(previous_file)).await,
+            fs::read(current.join(file)).await,
+        ) {
+            (Ok(a), Ok(b)) => (a, b),
+            _ => {
+                report.unreadable.push(url.clone());
+                continue;
+            }
+        };
+        let (score, image) = match tokio::task::spawn_blocking(move || diff_images(&a, &b)).await {
+            Ok(Some(x)) => x,
+            _ => {
+                report.unreadable.push(url.clone());
+                continue;
+            }
+        };
+        if score <= threshold {
+            continue;
+        }
+        let diff = format!("diff/{}", file);
+        if fs::write(current.join(&diff), image).await.is_err() {
+            return Err("Error in writing Diff Image".to_string());
+        }
+        report.changed.push(VisualChange {
+            url: url.clone(),
+            score,
+            previous: previous_file.clone(),
+            current: file.clone(),
+            diff,
+        });
+    }
+    report.removed = previous_files
+        .into_iter()
+        .filter(|(x, _)| !current_files.contains_key(x))
+        .map(|(x, _)| x)
+        .collect();
+    report
+        .changed
+        .sort_by(|a, b| b.score.total_cmp(&a.score).then(a.url.cmp(&b.url)));
+
+    if file_handler::write_json_file(current, "visual_diff.json", &report)
+        .await
+        .is_err()
+    {
+        return Err("Error in writing Visual Diff".to_string());
+    }
+    Ok(report)
+}
+
+/// Function to get the screenshot file of every url, sorted by url.
+fn screenshot_files(records: Vec<crate::selenium::ScreenshotRecord>) -> BTreeMap<String, String> {
+    records.into_iter().map(|x| (x.url, x.file)).collect()
+}
+
+/// Luminance of the pixels of an image, between 0 for black and 255 for white.
+struct Luma {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Luma {
+    /// Function to decode a png into its luminance.
+    /// Transparent pixels are drawn over white.
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().ok()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).ok()?;
+        let channels = info.color_type.samples();
+        let (width, height) = (info.width as usize, info.height as usize);
+        let pixels = buf[..info.line_size * height]
+            .chunks(info.line_size)
+            .flat_map(|x| x[..width * channels].chunks(channels))
+            .map(|x| {
+                let (luma, alpha) = match *x {
+                    [l] => (l as f64, 255.0),
+                    [l, a] => (l as f64, a as f64),
+                    [r, g, b] => (
+                        0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64,
+                        255.0,
+                    ),
+                    [r, g, b, a] => (
+                        0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64,
+                        a as f64,
+                    ),
+                    _ => (255.0, 0.0),
+                };
+                (alpha * luma / 255.0 + 255.0 - alpha).round() as u8
+            })
+            .collect();
+        Some(Luma {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Function to get the luminance of a pixel, between 0 for black and 1 for white.
+    fn get(&self, x: usize, y: usize) -> Option<f64> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[y * self.width + x] as f64 / 255.0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Function to compare two png screenshots.
+/// Returns the share of the area which changed and the diff image as png.
+fn diff_images(previous: &[u8], current: &[u8]) -> Option<(f64, Vec<u8>)> {
+    let a = Luma::decode(previous)?;
+    let b = Luma::decode(current)?;
+    let width = a.width.max(b.width);
+    let height = a.height.max(b.height);
+
+    let mut changed = vec![false; width * height];
+    let mut changed_area = 0;
+    for y in (0..height).step_by(BLOCK_SIZE) {
+        for x in (0..width).step_by(BLOCK_SIZE) {
+            let w = BLOCK_SIZE.min(width - x);
+            let h = BLOCK_SIZE.min(height - y);
+            if block_similarity(&a, &b, x, y, w, h).is_some_and(|s| s >= MIN_SIMILARITY) {
+                continue;
+            }
+            changed_area += w * h;
+            for row in y..y + h {
+                changed[row * width + x..row * width + x + w].fill(true);
+            }
+        }
+    }
+
+    let mut image = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let luma = b.get(x, y).or_else(|| a.get(x, y)).unwrap_or(1.0);
+            let pixel = if changed[y * width + x] {
+                [255, (luma * 160.0) as u8, (luma * 160.0) as u8]
+            } else {
+                [(128.0 + luma * 127.0) as u8; 3]
+            };
+            image.extend_from_slice(&pixel);
+        }
+    }
+    let png = encode_png(width as u32, height as u32, &image)?;
+    Some((changed_area as f64 / (width * height).max(1) as f64, png))
+}
+
+/// Function to get the structural similarity of a block of two images.
+/// Returns None if the block is not inside both of them.
+fn block_similarity(a: &Luma, b: &Luma, x: usize, y: usize, w: usize, h: usize) -> Option<f64> {
+    if x + w > a.width.min(b.width) || y + h > a.height.min(b.height) {
+        return None;
+    }
+    let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for row in y..y + h {
+        let pixels_a = &a.pixels[row * a.width + x..row * a.width + x + w];
+        let pixels_b = &b.pixels[row * b.width + x..row * b.width + x + w];
+        for (pa, pb) in pixels_a.iter().zip(pixels_b) {
+            let (pa, pb) = (*pa as f64 / 255.0, *pb as f64 / 255.0);
+            sum_a += pa;
+            sum_b += pb;
+            sum_aa += pa * pa;
+            sum_bb += pb * pb;
+            sum_ab += pa * pb;
+        }
+    }
+    let n = (w * h) as f64;
+    let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+    let var_a = sum_aa / n - mean_a * mean_a;
+    let var_b = sum_bb / n - mean_b * mean_b;
+    let covar = sum_ab / n - mean_a * mean_b;
+    Some(
+        ((2.0 * mean_a * mean_b + C1) * (2.0 * covar + C2))
+            / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2)),
+    )
+}
+
+/// Function to encode rgb pixels as png.
+fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Option<Vec<u8>> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().ok()?;
+    writer.write_image_data(rgb).ok()?;
+    writer.finish().ok()?;
+    Some(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selenium::ScreenshotRecord;
+
+    /// Function to draw a png of the given size, white with a black square.
+    fn draw(width: usize, height: usize, square: usize) -> Vec<u8> {
+        let mut rgb = vec![255; width * height * 3];
+        for y in 0..square {
+            for x in 0..square {
+                rgb[(y * width + x) * 3..(y * width + x) * 3 + 3].fill(0);
+            }
+        }
+        encode_png(width as u32, height as u32, &rgb).unwrap()
+    }
+
+    async fn write_folder(folder: &PathBuf, pages: &[(&str, &str, Vec<u8>)]) {
+        fs::create_dir_all(folder).await.unwrap();
+        let mut records = Vec::new();
+        for (url, file, png) in pages {
+            fs::write(folder.join(file), png).await.unwrap();
+            records.push(ScreenshotRecord {
+                file: file.to_string(),
+                dom: None,
+                url: url.to_string(),
+                timestamp: 0,
+                words: Vec::new(),
+                rules: Vec::new(),
+                viewport: None,
+                title: String::new(),
+            });
+        }
+        file_handler::write_json_file(folder.clone(), file_handler::INDEX_FILE_NAME, &records)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn diff_scores() {
+        let (score, _) = diff_images(&draw(32, 32, 8), &draw(32, 32, 8)).unwrap();
+        assert_eq!(score, 0.0);
+        let (score, _) = diff_images(&draw(32, 32, 8), &draw(32, 32, 16)).unwrap();
+        assert_eq!(score, 3.0 / 16.0);
+        let (score, png) = diff_images(&draw(32, 16, 0), &draw(32, 32, 0)).unwrap();
+        assert_eq!(score, 0.5);
+        let diff = Luma::decode(&png).unwrap();
+        assert_eq!((diff.width, diff.height), (32, 32));
+        assert!(diff_images(b"not a png", &draw(8, 8, 0)).is_none());
+    }
+
+    #[tokio::test]
+    async fn compare_folders() {
+        let root = std::env::temp_dir().join(format!("crate-visual-diff-{}", std::process::id()));
+        let (previous, current) = (root.join("previous"), root.join("current"));
+        write_folder(
+            &previous,
+            &[
+                ("https://a.com/", "a.png", draw(32, 32, 8)),
+                ("https://a.com/same", "same.png", draw(32, 32, 8)),
+                ("https://a.com/old", "old.png", draw(32, 32, 8)),
+            ],
+        )
+        .await;
+        write_folder(
+            &current,
+            &[
+                ("https://a.com/", "a.png", draw(32, 32, 32)),
+                ("https://a.com/same", "same.png", draw(32, 32, 8)),
+                ("https://a.com/new", "new.png", draw(32, 32, 8)),
+            ],
+        )
+        .await;
+
+        let report = compare_screenshots(previous, current.clone(), 0.1)
+            .await
+            .unwrap();
+        let written = (
+            current.join("diff/a.png").exists(),
+            current.join("visual_diff.json").exists(),
+        );
+        let _ = fs::remove_dir_all(&root).await;
+
+        assert_eq!(written, (true, true));
+
+        assert_eq!(report.changed.len(), 1);
+        assert_eq!(report.changed[0].url, "https://a.com/");
+        assert_eq!(report.changed[0].score, 15.0 / 16.0);
+        assert_eq!(report.changed[0].diff, "diff/a.png");
+        assert_eq!(report.added, vec!["https://a.com/new"]);
+        assert_eq!(report.removed, vec!["https://a.com/old"]);
+        assert!(report.unreadable.is_empty());
+    }
+}